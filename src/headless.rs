@@ -0,0 +1,40 @@
+use crate::Build;
+use crate::Target;
+use crate::math::{DiceCache, calc_build_stats};
+
+pub const FLAG: &str = "--headless";
+
+/// Prints the stats of a saved build as JSON, for scripting without the GUI.
+/// Expects `<build.json> [ac]`, the AC defaults to the one the GUI starts with.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let [path, rest @ ..] = args else {
+        return Err(format!("usage: dnd {FLAG} <build.json> [ac]"));
+    };
+    let target = match rest {
+        [] => Target::default(),
+        [ac] => Target {
+            ac: ac
+                .parse()
+                .map_err(|err| format!("invalid ac {ac}: {err}"))?,
+            ..Default::default()
+        },
+        _ => return Err(format!("usage: dnd {FLAG} <build.json> [ac]")),
+    };
+
+    let json =
+        std::fs::read_to_string(path).map_err(|err| format!("failed to read {path}: {err}"))?;
+    let build: Build =
+        serde_json::from_str(&json).map_err(|err| format!("failed to parse {path}: {err}"))?;
+
+    let stats = calc_build_stats(&build, &target, 0, &mut DiceCache::default());
+    let [p25, p50, p75, p95] = stats.percentiles([0.25, 0.5, 0.75, 0.95]);
+    let output = serde_json::json!({
+        "ac": target.ac,
+        "mean": stats.mean,
+        "std_dev": stats.std_dev,
+        "percentiles": { "25": p25, "50": p50, "75": p75, "95": p95 },
+        "pmf": stats.pmf,
+    });
+    println!("{output:#}");
+    Ok(())
+}
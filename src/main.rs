@@ -2,62 +2,59 @@
 
 use egui_plot::{Bar, BarChart, Corner, Legend, Line, Plot, PlotPoint, PlotPoints, Text, VLine};
 use std::collections::HashMap;
+use std::ops::RangeInclusive;
 use std::sync::mpsc::{self, *};
 
 use eframe::egui::{self, Align2, Color32, RichText, Vec2};
-use eframe::egui::{Stroke, Ui};
+use eframe::egui::{Key, Modifiers, Stroke, Ui};
+use egui_extras::{Column, TableBuilder};
 
+mod headless;
 mod math;
+mod palette;
+mod parse;
+mod presets;
 use math::*;
+use palette::Palette;
 
+// Default AC range for the mean dmg plots.
 const AC_MIN: u8 = 10;
-const AC_MAX: u8 = 24;
+const AC_MAX: u8 = 23;
+// Highest AC the mean dmg plots can go up to.
+const AC_LIMIT: u8 = 40;
+
+// Font sizes at a UI scale of 1, the scale setting zooms all of them together.
+const TITLE_SIZE: f32 = 35.0;
+// Build names and the section headings.
+const HEADING_SIZE: f32 = 24.0;
+const PLOT_TITLE_SIZE: f32 = 20.0;
+// Target settings.
+const SETTINGS_TEXT_SIZE: f32 = 20.0;
+// Attack inputs and the rest of a build box.
+const BUILD_TEXT_SIZE: f32 = 18.0;
+// Stats listed under a build.
+const STAT_TEXT_SIZE: f32 = 15.0;
+
+const MAX_BUILDS: usize = 4;
+// Used wherever several builds share a plot.
+const BUILD_COLORS: [Color32; MAX_BUILDS] = [
+    Color32::from_rgb(110, 90, 230),
+    Color32::from_rgb(230, 140, 50),
+    Color32::from_rgb(60, 180, 110),
+    Color32::from_rgb(210, 70, 120),
+];
+
+const MONTE_CARLO_SAMPLES: u32 = 100_000;
+const MONTE_CARLO_SEED: u64 = 2024;
 
 fn main() -> eframe::Result {
-    // MaybeUninit this.
-    // Oh wait you cant move out of array.
-    let mut stats_senders = Vec::new();
-    let mut stats_receivers = Vec::new();
-    for _ in 0..2 {
-        let (enque_stats_tx, enque_stats_rx) = mpsc::channel();
-        let (stats_tx, stats_rx) = mpsc::channel();
-        // We mainly do this because calculating mean dmg for a range of possible ACs
-        // is terribly slow and we might as well offload all the math to a separate thread.
-        std::thread::spawn(move || {
-            loop {
-                match enque_stats_rx.recv() {
-                    Ok((build, ac, min_dmg)) => {
-                        let stats = calc_build_stats(&build, ac, min_dmg);
-                        stats_tx.send(stats).unwrap();
-                    }
-                    Err(_) => return,
-                }
-            }
-        });
-        stats_senders.push(enque_stats_tx);
-        stats_receivers.push(stats_rx);
-    }
-
-    let mut means_senders = Vec::new();
-    let mut means_receivers = Vec::new();
-    for _ in 0..2 {
-        let (enque_means_tx, enque_means_rx) = mpsc::channel();
-        let (means_tx, means_rx) = mpsc::channel();
-        std::thread::spawn(move || {
-            loop {
-                match enque_means_rx.recv() {
-                    Ok(build) => {
-                        // This could have been faster if it didn't recompute everything
-                        // from scratch for no reason.
-                        let stats = calc_build_means(&build);
-                        means_tx.send(stats).unwrap();
-                    }
-                    Err(_) => return,
-                }
-            }
-        });
-        means_senders.push(enque_means_tx);
-        means_receivers.push(means_rx);
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some(headless::FLAG) {
+        if let Err(err) = headless::run(&args[1..]) {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+        return Ok(());
     }
 
     let options = eframe::NativeOptions {
@@ -70,38 +67,29 @@ fn main() -> eframe::Result {
         Box::new(|cc| {
             egui_extras::install_image_loaders(&cc.egui_ctx);
 
-            Ok(Box::new(Dnd {
-                build_a: Build::default(),
-                build_b: Build::default(),
-
-                stats_a: Stats::default(),
-                stats_b: Stats::default(),
-
-                means_a: Vec::new(),
-                means_b: Vec::new(),
-
-                stats_rx_a: stats_receivers.pop().unwrap(),
-                stats_rx_b: stats_receivers.pop().unwrap(),
-
-                stats_tx_a: stats_senders.pop().unwrap(),
-                stats_tx_b: stats_senders.pop().unwrap(),
-
-                means_rx_a: means_receivers.pop().unwrap(),
-                means_rx_b: means_receivers.pop().unwrap(),
-
-                means_tx_a: means_senders.pop().unwrap(),
-                means_tx_b: means_senders.pop().unwrap(),
-
-                sim_ac: 18,
-                desired_min_dmg: 15,
-                changed_a: true,
-                changed_b: true,
-            }))
+            let mut dnd = Dnd::default();
+            // Pick up where the last run left off, a fresh install just keeps the defaults.
+            if let Some(session) = cc
+                .storage
+                .and_then(|storage| eframe::get_value::<Session>(storage, SESSION_KEY))
+            {
+                dnd.load_session(session);
+            }
+            if let Some(dark_mode) = cc
+                .storage
+                .and_then(|storage| eframe::get_value::<bool>(storage, DARK_MODE_KEY))
+            {
+                dnd.dark_mode = dark_mode;
+            }
+            Ok(Box::new(dnd))
         }),
     )
 }
 
-#[derive(Debug, Copy, Clone)]
+// Dmg dice only, the d20 of the attack roll is never one of these.
+// Stored as the number of sides so saved builds read naturally, e.g. `[8, 2, "Fire"]`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(into = "u32", try_from = "u32")]
 enum Die {
     D4 = 4,
     D6 = 6,
@@ -110,396 +98,3136 @@ enum Die {
     D20 = 20,
 }
 
-#[derive(Debug, Copy, Clone)]
+impl From<Die> for u32 {
+    fn from(die: Die) -> Self {
+        die as u32
+    }
+}
+
+impl TryFrom<u32> for Die {
+    type Error = String;
+
+    fn try_from(sides: u32) -> Result<Self, Self::Error> {
+        match sides {
+            4 => Ok(Die::D4),
+            6 => Ok(Die::D6),
+            8 => Ok(Die::D8),
+            10 => Ok(Die::D10),
+            20 => Ok(Die::D20),
+            _ => Err(format!("there is no d{sides}")),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+enum DamageType {
+    Slashing,
+    Piercing,
+    Bludgeoning,
+    Fire,
+    Cold,
+    Lightning,
+    Thunder,
+    Acid,
+    Poison,
+    Necrotic,
+    Radiant,
+    Force,
+    Psychic,
+}
+
+const DAMAGE_TYPES: [DamageType; 13] = [
+    DamageType::Slashing,
+    DamageType::Piercing,
+    DamageType::Bludgeoning,
+    DamageType::Fire,
+    DamageType::Cold,
+    DamageType::Lightning,
+    DamageType::Thunder,
+    DamageType::Acid,
+    DamageType::Poison,
+    DamageType::Necrotic,
+    DamageType::Radiant,
+    DamageType::Force,
+    DamageType::Psychic,
+];
+
+impl DamageType {
+    fn label(self) -> &'static str {
+        match self {
+            DamageType::Slashing => "Slashing",
+            DamageType::Piercing => "Piercing",
+            DamageType::Bludgeoning => "Bludgeoning",
+            DamageType::Fire => "Fire",
+            DamageType::Cold => "Cold",
+            DamageType::Lightning => "Lightning",
+            DamageType::Thunder => "Thunder",
+            DamageType::Acid => "Acid",
+            DamageType::Poison => "Poison",
+            DamageType::Necrotic => "Necrotic",
+            DamageType::Radiant => "Radiant",
+            DamageType::Force => "Force",
+            DamageType::Psychic => "Psychic",
+        }
+    }
+}
+
+/// How the target takes a given damage type.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+enum Defense {
+    Normal,
+    Resistant,
+    Immune,
+}
+
+impl Defense {
+    fn label(self) -> &'static str {
+        match self {
+            Defense::Normal => "Normal",
+            Defense::Resistant => "Resistant",
+            Defense::Immune => "Immune",
+        }
+    }
+}
+
+/// A probability that can still be part of a hashed key, it's compared by its bits.
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+struct Chance(f64);
+
+// Going by the bits keeps this in line with `Hash` and makes NaN equal to itself, which `Eq`
+// needs.
+impl PartialEq for Chance {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.to_bits() == other.0.to_bits()
+    }
+}
+
+impl Eq for Chance {}
+
+impl std::hash::Hash for Chance {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state);
+    }
+}
+
+/// How many d20s are rolled for the attack and which one is kept.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+enum RollMode {
+    Normal,
+    /// 2 dice for regular advantage, 3 for Elven Accuracy.
+    Advantage {
+        dice: u8,
+    },
+    Disadvantage,
+}
+
+impl RollMode {
+    /// Turns a chance for a single d20 into the chance for the kept die.
+    fn chance(self, single: f64) -> f64 {
+        match self {
+            RollMode::Normal => single,
+            RollMode::Advantage { dice } => 1.0 - (1.0 - single).powi(dice as i32),
+            RollMode::Disadvantage => single * single,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            RollMode::Normal => "Normal",
+            RollMode::Advantage { dice: 3 } => "Elven Accuracy",
+            RollMode::Advantage { .. } => "Advantage",
+            RollMode::Disadvantage => "Disadvantage",
+        }
+    }
+}
+
+/// What happens to the dmg dice of an attack on a crit.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+enum CritMultiplier {
+    DoubleDice,
+    TripleDice,
+    /// Every die deals its max and is then rolled once more.
+    MaxPlusRoll,
+}
+
+impl CritMultiplier {
+    const ALL: [CritMultiplier; 3] = [
+        CritMultiplier::DoubleDice,
+        CritMultiplier::TripleDice,
+        CritMultiplier::MaxPlusRoll,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            CritMultiplier::DoubleDice => "Double dice",
+            CritMultiplier::TripleDice => "Triple dice",
+            CritMultiplier::MaxPlusRoll => "Max + roll",
+        }
+    }
+}
+
+/// Situations that grant advantage or disadvantage on an attack, on top of its roll mode.
+#[derive(
+    Debug, Default, Copy, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize,
+)]
+#[serde(default)]
+struct RollSources {
+    // Advantage.
+    flanking: bool,
+    // Only melee attacks get advantage against a prone target.
+    prone_target: bool,
+    hidden: bool,
+    // Disadvantage.
+    long_range: bool,
+    attacker_prone: bool,
+}
+
+/// Label of a situation along with the flag it sets.
+type RollSource = (&'static str, fn(&mut RollSources) -> &mut bool);
+
+impl RollSources {
+    const ADVANTAGE: [RollSource; 3] = [
+        ("Flanking", |s| &mut s.flanking),
+        ("Prone target (melee)", |s| &mut s.prone_target),
+        ("Hidden attacker", |s| &mut s.hidden),
+    ];
+    const DISADVANTAGE: [RollSource; 2] = [
+        ("Long range", |s| &mut s.long_range),
+        ("Attacker prone", |s| &mut s.attacker_prone),
+    ];
+
+    fn any_advantage(&self) -> bool {
+        self.flanking || self.prone_target || self.hidden
+    }
+
+    fn any_disadvantage(&self) -> bool {
+        self.long_range || self.attacker_prone
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+struct Save {
+    dc: i32,
+    half_on_success: bool,
+    // Some monster abilities deal nothing at all on a natural 20, even if a regular
+    // success would still deal half.
+    no_damage_on_crit_save: bool,
+    // Number of targets caught in the area, each of them saves and takes dmg on its own.
+    targets: u32,
+}
+
+impl Default for Save {
+    fn default() -> Self {
+        Self {
+            dc: 15,
+            half_on_success: true,
+            no_damage_on_crit_save: false,
+            targets: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
 struct Attack {
+    // Only for the user, empty means the attack is labeled by its position.
+    name: String,
     ab: i32,
     flat: u8,
-    dice: [(Die, u8); 5],
+    flat_type: DamageType,
+    // Dmg dice as (die, count, type). A D20 here is rolled for dmg like any other die, it has
+    // nothing to do with the attack roll, which always uses its own d20.
+    dice: [(Die, u8, DamageType); 5],
+    // Dice that don't exist physically, like a d3, as (sides, count, type).
+    custom_dice: Vec<(u32, u8, DamageType)>,
+    roll_mode: RollMode,
+    roll_sources: RollSources,
+    // When set the target rolls a save instead of the attack rolling to hit.
+    save: Option<Save>,
+    // Sharpshooter / Great Weapon Master, -5 to hit for +10 dmg.
+    power_attack: bool,
+    // Lowest natural roll that crits, 19 for Champion and keen weapons.
+    crit_range: u8,
+    // Only changes the attack's own dice, once per turn dice always double.
+    crit_multiplier: CritMultiplier,
+    // Extra flat dmg that only crits deal, it is never doubled.
+    crit_flat_bonus: u8,
+    // Lower faces on the dmg dice count as this one, 2 for Elemental Adept.
+    min_face: u8,
+    // A miss gets attacked again once.
+    reroll_on_miss: bool,
+    // Bonus attack that is only made if an earlier attack this turn crit.
+    on_crit_only: bool,
+    // Once per rest, like a Smite or Action Surge, so it only happens in the first round.
+    nova: bool,
+    // The lowest dmg die gets rolled once more, only for dice of a single size and type.
+    reroll_lowest: bool,
+    // Dmg dice that roll their max face get rolled again and added, see `EXPLODE_LEVELS`.
+    explode: bool,
+    // The dmg of a hit is rolled twice and both rolls are added up, crits included.
+    roll_twice_sum: bool,
+    // Whether the flat dmg is added to both of those rolls or only once. The power attack and
+    // crit bonuses are always added once.
+    roll_twice_flat: bool,
+    // Fixed chance to hit (crits included) instead of comparing the roll against the AC.
+    hit_override: Option<Chance>,
 }
 
 impl Default for Attack {
     fn default() -> Self {
         Self {
+            name: String::new(),
             ab: 10,
             flat: 4,
+            flat_type: DamageType::Slashing,
             dice: [
-                (Die::D4, 2),
-                (Die::D6, 0),
-                (Die::D8, 1),
-                (Die::D10, 0),
-                (Die::D20, 0),
+                (Die::D4, 2, DamageType::Slashing),
+                (Die::D6, 0, DamageType::Slashing),
+                (Die::D8, 1, DamageType::Slashing),
+                (Die::D10, 0, DamageType::Slashing),
+                (Die::D20, 0, DamageType::Slashing),
             ],
+            custom_dice: Vec::new(),
+            roll_mode: RollMode::Normal,
+            roll_sources: RollSources::default(),
+            save: None,
+            power_attack: false,
+            crit_range: 20,
+            crit_multiplier: CritMultiplier::DoubleDice,
+            crit_flat_bonus: 0,
+            min_face: 1,
+            reroll_on_miss: false,
+            on_crit_only: false,
+            nova: false,
+            reroll_lowest: false,
+            explode: false,
+            roll_twice_sum: false,
+            roll_twice_flat: false,
+            hit_override: None,
+        }
+    }
+}
+
+impl Attack {
+    /// Both the regular and the custom dice as (sides, count, type).
+    fn all_dice(&self) -> impl Iterator<Item = (u32, u8, DamageType)> {
+        let dice = self
+            .dice
+            .iter()
+            .map(|&(die, count, dmg_type)| (die as u32, count, dmg_type));
+        dice.chain(self.custom_dice.iter().copied())
+    }
+
+    /// Size and type of the dice when the lowest of them gets rerolled. Only pools where all
+    /// of the dice are the same are supported, for anything else this is `None`.
+    fn reroll_lowest_pool(&self) -> Option<(u32, DamageType)> {
+        if !self.reroll_lowest {
+            return None;
+        }
+        let mut pools = self
+            .all_dice()
+            .filter(|&(_, count, _)| count > 0)
+            .map(|(sides, _, dmg_type)| (sides, dmg_type));
+        let pool = pools.next()?;
+        pools.all(|other| other == pool).then_some(pool)
+    }
+
+    /// Roll mode once the situational sources are added in. Any advantage together with
+    /// any disadvantage cancels out, no matter how many of each there are.
+    fn effective_roll_mode(&self) -> RollMode {
+        let advantage = self.roll_sources.any_advantage()
+            || matches!(self.roll_mode, RollMode::Advantage { .. });
+        let disadvantage =
+            self.roll_sources.any_disadvantage() || self.roll_mode == RollMode::Disadvantage;
+        match (advantage, disadvantage) {
+            (true, true) | (false, false) => RollMode::Normal,
+            (true, false) => match self.roll_mode {
+                RollMode::Advantage { dice } => RollMode::Advantage { dice },
+                _ => RollMode::Advantage { dice: 2 },
+            },
+            (false, true) => RollMode::Disadvantage,
+        }
+    }
+
+    fn label(&self, idx: usize) -> String {
+        if self.name.is_empty() {
+            format!("Attack {}", idx + 1)
+        } else {
+            self.name.clone()
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
 struct Build {
+    // Shown next to the build letter, only for the user.
+    name: String,
     attacks: Vec<Attack>,
     savage: bool,
+    // Savage Attacker keeps the better roll of every die instead of the better total.
+    savage_per_die: bool,
     crit_enabled: bool,
+    // Adds a d4 to every attack roll.
+    bless: bool,
+    // Subtracts a d4 from every attack roll.
+    bane: bool,
+    // Halfling Luck, natural 1s on attack rolls get rerolled once. With multiple d20s
+    // every 1 is treated as rerolled.
+    lucky: bool,
+    // Sneak Attack and similar riders, added once per turn to the first attack that hits.
+    sneak_dice: [(Die, u8); 5],
+    // Flat dmg added once per turn to the first attack that hits, like the dice above.
+    first_hit_bonus: u32,
+    // Actions the build spends per round, bonus actions included. Only used to show the dmg
+    // per action, so builds of different action economy can be compared.
+    actions: u32,
+    // Free-form notes for the user, never used in any calculations.
+    notes: String,
 }
 
 impl Default for Build {
     fn default() -> Self {
         Self {
+            name: String::new(),
             attacks: vec![Attack::default()],
             savage: false,
+            savage_per_die: false,
             crit_enabled: true,
+            bless: false,
+            bane: false,
+            lucky: false,
+            sneak_dice: [
+                (Die::D4, 0),
+                (Die::D6, 0),
+                (Die::D8, 0),
+                (Die::D10, 0),
+                (Die::D20, 0),
+            ],
+            first_hit_bonus: 0,
+            actions: 1,
+            notes: String::new(),
         }
     }
 }
 
-struct Dnd {
-    build_a: Build,
-    build_b: Build,
-    stats_a: Stats,
-    stats_b: Stats,
-    // We store these outside of core stats because it takes much longer to compute and we dont
-    // want to stall the other data.
-    means_a: Vec<f64>,
-    means_b: Vec<f64>,
+/// How an attack roll total is compared against the target's AC.
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+enum AcComparison {
+    /// 5e rule, a total equal to the AC is a hit.
+    MeetsOrBeats,
+    /// Homebrew rule, the total has to be strictly higher than the AC.
+    MustExceed,
+}
 
-    stats_rx_a: Receiver<Stats>,
-    stats_rx_b: Receiver<Stats>,
+impl AcComparison {
+    fn hits(self, total: i32, ac: i32) -> bool {
+        match self {
+            AcComparison::MeetsOrBeats => total >= ac,
+            AcComparison::MustExceed => total > ac,
+        }
+    }
 
-    stats_tx_a: Sender<(Build, u8, u32)>,
-    stats_tx_b: Sender<(Build, u8, u32)>,
+    fn label(self) -> &'static str {
+        match self {
+            AcComparison::MeetsOrBeats => "Meets or beats AC",
+            AcComparison::MustExceed => "Must exceed AC",
+        }
+    }
+}
 
-    means_rx_a: Receiver<Vec<f64>>,
-    means_rx_b: Receiver<Vec<f64>>,
+/// Lets the attack rolls be skipped, to look at the dmg dice on their own.
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+enum HitRoll {
+    /// Attacks are rolled against the AC as usual.
+    Rolled,
+    /// Every attack hits, crits still happen as often as the d20 rolls them.
+    AlwaysHits,
+    /// Every attack hits without ever critting.
+    AlwaysHitsNoCrits,
+}
 
-    means_tx_a: Sender<Build>,
-    means_tx_b: Sender<Build>,
+impl HitRoll {
+    const ALL: [HitRoll; 3] = [
+        HitRoll::Rolled,
+        HitRoll::AlwaysHits,
+        HitRoll::AlwaysHitsNoCrits,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            HitRoll::Rolled => "Roll to hit",
+            HitRoll::AlwaysHits => "Always hit",
+            HitRoll::AlwaysHitsNoCrits => "Always hit, no crits",
+        }
+    }
+}
 
-    sim_ac: u8,
-    desired_min_dmg: u32,
-    changed_a: bool,
-    changed_b: bool,
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+struct Target {
+    ac: u8,
+    ac_comparison: AcComparison,
+    // Attacks ignore the AC, the miss chance and rerolls when not rolled. Saves are unaffected.
+    hit_roll: HitRoll,
+    save_bonus: i32,
+    // Halves the total dmg, rounding down.
+    resistance: bool,
+    // Doubles the total dmg.
+    vulnerable: bool,
+    // Flat damage reduction applied to every single hit.
+    dr: u32,
+    // Concealment and the like, rolled after an attack hits. Saves ignore it.
+    miss_chance: f64,
+    // Indexed by `DamageType`.
+    defenses: [Defense; DAMAGE_TYPES.len()],
+    // Only used for the chance to drop the target in a single round.
+    hp: u32,
 }
 
-impl eframe::App for Dnd {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        if self.changed_a {
-            self.changed_a = false;
-            self.stats_tx_a
-                .send((self.build_a.clone(), self.sim_ac, self.desired_min_dmg))
-                .unwrap();
-            self.means_tx_a.send(self.build_a.clone()).unwrap();
+impl Default for Target {
+    fn default() -> Self {
+        Self {
+            ac: 18,
+            ac_comparison: AcComparison::MeetsOrBeats,
+            hit_roll: HitRoll::Rolled,
+            save_bonus: 3,
+            resistance: false,
+            vulnerable: false,
+            dr: 0,
+            miss_chance: 0.0,
+            defenses: [Defense::Normal; DAMAGE_TYPES.len()],
+            hp: 40,
+        }
+    }
+}
+
+impl Build {
+    fn empty() -> Self {
+        Self {
+            attacks: Vec::new(),
+            ..Default::default()
         }
+    }
+
+    fn add_attack(&mut self) {
+        let prev_or_def = self.attacks.last().cloned().unwrap_or(Attack::default());
+        self.attacks.push(prev_or_def);
+    }
+}
+
+/// Undo and redo steps of a single build. Edits are only recorded once the user is done
+/// with them, so dragging a value ends up as one step instead of one per frame.
+struct EditHistory {
+    undo: Vec<Build>,
+    redo: Vec<Build>,
+    // The build as of the last recorded step.
+    recorded: Build,
+}
 
-        if self.changed_b {
-            self.changed_b = false;
-            self.stats_tx_b
-                .send((self.build_b.clone(), self.sim_ac, self.desired_min_dmg))
-                .unwrap();
-            self.means_tx_b.send(self.build_b.clone()).unwrap();
+impl EditHistory {
+    fn new(build: &Build) -> Self {
+        Self {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            recorded: build.clone(),
         }
+    }
 
-        if let Ok(stats) = self.stats_rx_a.try_recv() {
-            self.stats_a = stats;
+    /// Turns the edits made since the last step into a new step. Returns whether there
+    /// were any.
+    fn record(&mut self, build: &Build) -> bool {
+        if *build == self.recorded {
+            return false;
         }
+        self.undo
+            .push(std::mem::replace(&mut self.recorded, build.clone()));
+        self.redo.clear();
+        true
+    }
+
+    fn can_undo(&self, build: &Build) -> bool {
+        !self.undo.is_empty() || *build != self.recorded
+    }
+
+    fn can_redo(&self, build: &Build) -> bool {
+        !self.redo.is_empty() && *build == self.recorded
+    }
 
-        if let Ok(stats) = self.stats_rx_b.try_recv() {
-            self.stats_b = stats;
+    /// Returns whether the build changed.
+    fn undo(&mut self, build: &mut Build) -> bool {
+        self.record(build);
+        let Some(prev) = self.undo.pop() else {
+            return false;
+        };
+        self.redo.push(std::mem::replace(build, prev));
+        self.recorded = build.clone();
+        true
+    }
+
+    /// Returns whether the build changed.
+    fn redo(&mut self, build: &mut Build) -> bool {
+        // Fresh edits start a new branch, there is nothing left to redo.
+        if self.record(build) {
+            return false;
         }
+        let Some(next) = self.redo.pop() else {
+            return false;
+        };
+        self.undo.push(std::mem::replace(build, next));
+        self.recorded = build.clone();
+        true
+    }
+}
+
+/// Asks where to save `value` as JSON. `what` is only used in error messages.
+fn save_json(value: &impl serde::Serialize, file_name: &str, what: &str) {
+    let Some(path) = rfd::FileDialog::new()
+        .add_filter("JSON", &["json"])
+        .set_file_name(file_name)
+        .save_file()
+    else {
+        return;
+    };
+
+    let result = serde_json::to_string_pretty(value)
+        .map_err(|err| err.to_string())
+        .and_then(|json| std::fs::write(&path, json).map_err(|err| err.to_string()));
+    if let Err(err) = result {
+        eprintln!("failed to save {what} to {}: {err}", path.display());
+    }
+}
 
-        if let Ok(means) = self.means_rx_a.try_recv() {
-            self.means_a = means;
-            self.stats_a.greater_then_chance = greater_than(&self.stats_a.pmf, &self.stats_b.pmf);
+/// Asks for a JSON file to load. `what` is only used in error messages.
+fn load_json<T: serde::de::DeserializeOwned>(what: &str) -> Option<T> {
+    let path = rfd::FileDialog::new()
+        .add_filter("JSON", &["json"])
+        .pick_file()?;
+
+    let result = std::fs::read_to_string(&path)
+        .map_err(|err| err.to_string())
+        .and_then(|json| serde_json::from_str(&json).map_err(|err| err.to_string()));
+    match result {
+        Ok(loaded) => Some(loaded),
+        Err(err) => {
+            eprintln!("failed to load {what} from {}: {err}", path.display());
+            None
         }
+    }
+}
+
+fn save_build(build: &Build) {
+    save_json(build, "build.json", "build");
+}
+
+/// Writes the dmg distribution as `damage,probability,cumulative` rows.
+fn export_csv(stats: &Stats) {
+    let Some(path) = rfd::FileDialog::new()
+        .add_filter("CSV", &["csv"])
+        .set_file_name("distribution.csv")
+        .save_file()
+    else {
+        return;
+    };
+
+    let mut csv = String::from("damage,probability,cumulative\n");
+    // Both are sorted by dmg and have an entry for every outcome. The fixed precision
+    // hides float accumulation noise, so the last cumulative value reads as exactly 1.
+    for ((dmg, prob), (_, cumulative)) in stats.pmf.iter().zip(&stats.cdf) {
+        csv.push_str(&format!("{dmg},{prob:.10},{cumulative:.10}\n"));
+    }
+
+    if let Err(err) = std::fs::write(&path, csv) {
+        eprintln!("failed to export csv to {}: {err}", path.display());
+    }
+}
+
+/// Writes the mean dmg of every build as `ac,mean_a,mean_b,...` rows, `means` laid out as
+/// `(name, first AC, means)` like on the plots. ACs a build has no mean for yet stay empty.
+fn export_ac_table(means: &[(String, u8, &[f64])]) {
+    let Some(path) = rfd::FileDialog::new()
+        .add_filter("CSV", &["csv"])
+        .set_file_name("ac_table.csv")
+        .save_file()
+    else {
+        return;
+    };
+
+    let acs = means
+        .iter()
+        .filter(|(_, _, means)| !means.is_empty())
+        .map(|&(_, ac_min, means)| (ac_min as usize, ac_min as usize + means.len() - 1));
+    let (Some(first), Some(last)) = (
+        acs.clone().map(|(first, _)| first).min(),
+        acs.map(|(_, last)| last).max(),
+    ) else {
+        return;
+    };
 
-        if let Ok(means) = self.means_rx_b.try_recv() {
-            self.stats_b.greater_then_chance = greater_than(&self.stats_b.pmf, &self.stats_a.pmf);
-            self.means_b = means;
+    let mut csv = String::from("ac");
+    for idx in 0..means.len() {
+        csv.push_str(&format!(",mean_{}", build_letter(idx).to_ascii_lowercase()));
+    }
+    csv.push('\n');
+    for ac in first..=last {
+        csv.push_str(&ac.to_string());
+        for &(_, ac_min, means) in means {
+            csv.push(',');
+            if let Some(mean) = ac.checked_sub(ac_min as usize).and_then(|i| means.get(i)) {
+                csv.push_str(&format!("{mean:.4}"));
+            }
         }
+        csv.push('\n');
+    }
 
-        egui::CentralPanel::default().show(ctx, |ui| {
-            egui::ScrollArea::vertical().show(ui, |ui| {
-                ui.label(RichText::new("DND build calculator").size(35.0));
-                ui.add_space(10.0);
+    if let Err(err) = std::fs::write(&path, csv) {
+        eprintln!("failed to export ac table to {}: {err}", path.display());
+    }
+}
 
-                let gap = 30.0;
-                let total_width = ui.max_rect().width() - 10.0;
-                let item_width = (total_width - gap) / 2.0;
-                ui.horizontal(|ui| {
-                    ui.spacing_mut().item_spacing.x = gap;
-                    build_box(
-                        ui,
-                        item_width,
-                        "Build A",
-                        &mut self.build_a,
-                        &self.stats_a,
-                        self.desired_min_dmg,
-                        &mut self.changed_a,
-                    );
-                    build_box(
-                        ui,
-                        item_width,
-                        "Build B",
-                        &mut self.build_b,
-                        &self.stats_b,
-                        self.desired_min_dmg,
-                        &mut self.changed_b,
-                    );
-                });
+/// Writes a Markdown summary of every build, its attacks and its main stats against the target.
+fn export_report(builds: &[BuildSlot], target: &Target, rounds: u32) {
+    let Some(path) = rfd::FileDialog::new()
+        .add_filter("Markdown", &["md"])
+        .set_file_name("report.md")
+        .save_file()
+    else {
+        return;
+    };
 
-                ui.add_space(20.0);
-                ui.scope(|ui| {
-                    let style = ui.style_mut();
-                    for (_text_style, font_id) in style.text_styles.iter_mut() {
-                        font_id.size = 20.0;
-                    }
+    let mut report = format!(
+        "# Damage report\n\nAgainst AC {} over {rounds} round(s).\n",
+        target.ac
+    );
+    for (idx, slot) in builds.iter().enumerate() {
+        let build = &slot.build;
+        report.push_str(&format!("\n## {}", build_name(idx)));
+        if !build.name.is_empty() {
+            report.push_str(&format!(": {}", build.name));
+        }
+        report.push_str("\n\n");
+        // Quoted so the notes can't break the structure of the report.
+        for line in build.notes.lines() {
+            report.push_str(&format!("> {line}\n"));
+        }
+        if !build.notes.is_empty() {
+            report.push('\n');
+        }
 
-                    ui.horizontal(|ui| {
-                        ui.label("Sim AC:");
-                        let changed = ui.add(egui::DragValue::new(&mut self.sim_ac)).changed();
-                        self.changed_a |= changed;
-                        self.changed_b |= changed;
+        for (i, attack) in build.attacks.iter().enumerate() {
+            let mut terms: Vec<String> = attack
+                .all_dice()
+                .filter(|&(_, count, _)| count > 0)
+                .map(|(sides, count, _)| format!("{count}d{sides}"))
+                .collect();
+            if attack.flat > 0 || terms.is_empty() {
+                terms.push(attack.flat.to_string());
+            }
+            let roll = match &attack.save {
+                Some(save) => format!("DC {} save", save.dc),
+                None => format!("{:+} to hit", attack.ab),
+            };
+            report.push_str(&format!(
+                "- {}: {roll}, {}\n",
+                attack.label(i),
+                terms.join(" + ")
+            ));
+        }
 
-                        ui.add_space(10.0);
-                        ui.label("Min desired dmg:");
-                        let changed = ui
-                            .add(egui::DragValue::new(&mut self.desired_min_dmg))
-                            .changed();
-                        self.changed_a |= changed;
-                        self.changed_b |= changed;
-                    });
-                });
-                ui.add_space(20.0);
+        let stats = &slot.stats;
+        report.push_str(&format!(
+            "\nMean damage {:.2}, standard deviation {:.2}, median {}, chance to hit at least \
+             once {:.1}%.\n",
+            stats.mean,
+            stats.std_dev,
+            stats.median,
+            stats.any_hit_chance * 100.0
+        ));
+    }
 
-                let plot_width = (total_width - gap) / 2.0;
-                ui.horizontal(|ui| {
-                    ui.spacing_mut().item_spacing.x = gap;
-                    let plot_size = Vec2::new(plot_width, 500.0);
-                    plot_pmf(ui, "Damage Distribution A", &self.stats_a.pmf, plot_size);
-                    plot_pmf(ui, "Damage Distribution B", &self.stats_b.pmf, plot_size);
-                });
+    if let Err(err) = std::fs::write(&path, report) {
+        eprintln!("failed to export report to {}: {err}", path.display());
+    }
+}
 
-                ui.add_space(15.0);
-                ui.horizontal(|ui| {
-                    ui.spacing_mut().item_spacing.x = gap;
-                    let plot_size = Vec2::new(plot_width, 500.0);
-                    plot_cdf(
-                        ui,
-                        "Cumulative Distribution A",
-                        &self.stats_a.cdf,
-                        plot_size,
-                    );
-                    plot_cdf(
-                        ui,
-                        "Cumulative Distrbuition B",
-                        &self.stats_b.cdf,
-                        plot_size,
-                    );
-                });
+/// Replaces the build with one picked from disk. Returns whether anything was loaded.
+fn load_build(build: &mut Build) -> bool {
+    match load_json("build") {
+        Some(loaded) => {
+            *build = loaded;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Key of the session eframe keeps around between runs.
+const SESSION_KEY: &str = "session";
+/// The theme is kept apart from the session, so loading a session file leaves it alone.
+const DARK_MODE_KEY: &str = "dark_mode";
+
+/// All builds along with the target and the rest of the settings they are compared
+/// under, so a whole comparison can be saved and brought back later.
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+struct Session {
+    builds: Vec<Build>,
+    target: Target,
+    desired_min_dmg: u32,
+    rounds: u32,
+    ac_range: (u8, u8),
+    pinned_acs: Vec<u8>,
+}
+
+/// A single build along with everything computed for it.
+struct BuildSlot {
+    build: Build,
+    history: EditHistory,
+    // Stats over all of the rounds, which is what gets displayed and compared.
+    stats: Stats,
+    // Stats of a single round, used for the encounter.
+    round_stats: Stats,
+    pinned_stats: Vec<Stats>,
+    // Empirical distribution, only computed when Monte Carlo mode is on.
+    simulated_pmf: Option<PMF>,
+    // Single upgrade that adds the most mean dmg per round at the target's AC, and that gain.
+    suggestion: Option<(String, f64)>,
+    // We store these outside of core stats because it takes much longer to compute and we dont
+    // want to stall the other data.
+    means: Vec<f64>,
+    // Std dev at every AC, same layout as `means`.
+    std_devs: Vec<f64>,
+    // Means of the same build with every power attack turned off, only present when
+    // the build uses power attacks at all.
+    no_power_means: Option<Vec<f64>>,
+    // AC from which each power attack stops paying off, in the same order as the attacks.
+    // Covers the same ACs as `means`, `None` when it pays off at all of them.
+    power_attack_break_even: Vec<Option<u8>>,
+    // AC of the first entry in `means`.
+    means_ac_min: u8,
+    // Chances to win, tie and lose against each of the builds, indexed the same way
+    // as `Dnd::builds`.
+    outcomes: Vec<(f64, f64, f64)>,
+    // Chances to deal strictly the most dmg of all builds and to tie for the most.
+    most_dmg: (f64, f64),
+    changed: bool,
+
+    stats_rx: Receiver<StatsResult>,
+    stats_tx: Sender<StatsRequest>,
+    means_rx: Receiver<MeansResult>,
+    means_tx: Sender<(Build, Target, RangeInclusive<u8>)>,
+}
+
+/// Everything the stats worker needs to compute the stats of a build.
+struct StatsRequest {
+    build: Build,
+    target: Target,
+    desired_min_dmg: u32,
+    pinned_acs: Vec<u8>,
+    monte_carlo: bool,
+    rounds: u32,
+}
+
+struct StatsResult {
+    stats: Stats,
+    round_stats: Stats,
+    pinned_stats: Vec<Stats>,
+    simulated_pmf: Option<PMF>,
+    suggestion: Option<(String, f64)>,
+}
+
+/// First AC, then the means and std devs from that AC on, the means without power attacks and
+/// the AC from which each power attack stops paying off.
+type MeansResult = (u8, Vec<f64>, Vec<f64>, Option<Vec<f64>>, Vec<Option<u8>>);
+
+impl BuildSlot {
+    fn new(build: Build) -> Self {
+        let (enque_stats_tx, enque_stats_rx) = mpsc::channel::<StatsRequest>();
+        let (stats_tx, stats_rx) = mpsc::channel();
+        // We mainly do this because calculating mean dmg for a range of possible ACs
+        // is terribly slow and we might as well offload all the math to a separate thread.
+        // Workers shut down once the slot (and with it the sender) gets dropped.
+        std::thread::spawn(move || {
+            let mut cache = DiceCache::default();
+            loop {
+                match enque_stats_rx.recv() {
+                    Ok(req) => {
+                        let StatsRequest {
+                            build,
+                            target,
+                            desired_min_dmg,
+                            pinned_acs,
+                            monte_carlo,
+                            rounds,
+                        } = req;
+                        let round_stats =
+                            calc_build_stats(&build, &target, desired_min_dmg, &mut cache);
+                        let result = StatsResult {
+                            stats: calc_rounds_stats(
+                                &round_stats,
+                                &build,
+                                rounds,
+                                &target,
+                                desired_min_dmg,
+                            ),
+                            round_stats,
+                            pinned_stats: calc_pinned_stats(
+                                &build,
+                                &target,
+                                desired_min_dmg,
+                                &pinned_acs,
+                                &mut cache,
+                            ),
+                            simulated_pmf: monte_carlo.then(|| {
+                                let simulate = |build: &Build| {
+                                    simulate_build(
+                                        build,
+                                        &target,
+                                        MONTE_CARLO_SAMPLES,
+                                        MONTE_CARLO_SEED,
+                                    )
+                                };
+                                let pmf = simulate(&build);
+                                let later = sustained_build(&build).map(|b| simulate(&b));
+                                nova_rounds(&pmf, later.as_ref().unwrap_or(&pmf), rounds)
+                            }),
+                            suggestion: suggest_improvement(&build, &target, &mut cache),
+                        };
+                        if stats_tx.send(result).is_err() {
+                            return;
+                        }
+                    }
+                    Err(_) => return,
+                }
+            }
+        });
+
+        let (enque_means_tx, enque_means_rx) =
+            mpsc::channel::<(Build, Target, RangeInclusive<u8>)>();
+        let (means_tx, means_rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut cache = DiceCache::default();
+            loop {
+                match enque_means_rx.recv() {
+                    Ok((build, target, acs)) => {
+                        let (means, std_devs): (Vec<_>, Vec<_>) =
+                            calc_build_means(&build, &target, acs.clone(), &mut cache)
+                                .into_iter()
+                                .unzip();
+                        let no_power_means =
+                            calc_no_power_attack_means(&build, &target, acs.clone(), &mut cache);
+                        let break_even = power_attack_break_even(&build, &target, acs.clone());
+                        if means_tx
+                            .send((*acs.start(), means, std_devs, no_power_means, break_even))
+                            .is_err()
+                        {
+                            return;
+                        }
+                    }
+                    Err(_) => return,
+                }
+            }
+        });
+
+        Self {
+            history: EditHistory::new(&build),
+            build,
+            stats: Stats::default(),
+            round_stats: Stats::default(),
+            pinned_stats: Vec::new(),
+            simulated_pmf: None,
+            suggestion: None,
+            means: Vec::new(),
+            std_devs: Vec::new(),
+            no_power_means: None,
+            power_attack_break_even: Vec::new(),
+            means_ac_min: AC_MIN,
+            outcomes: Vec::new(),
+            most_dmg: (0.0, 0.0),
+            changed: true,
+
+            stats_rx,
+            stats_tx: enque_stats_tx,
+            means_rx,
+            means_tx: enque_means_tx,
+        }
+    }
+}
+
+fn build_letter(idx: usize) -> char {
+    (b'A' + idx as u8) as char
+}
+
+fn build_name(idx: usize) -> String {
+    format!("Build {}", build_letter(idx))
+}
+
+struct Dnd {
+    builds: Vec<BuildSlot>,
+
+    target: Target,
+    desired_min_dmg: u32,
+    // Number of rounds the displayed distributions cover.
+    rounds: u32,
+    // Inclusive range of ACs on the mean dmg plots.
+    ac_range: (u8, u8),
+    // Extra percentile marked on the cdf plots.
+    percentile: f64,
+    // Overlays a simulated distribution on the exact one, as a sanity check.
+    monte_carlo: bool,
+    // Shows the dmg distributions with log10 chances, so the tails stay visible.
+    log_scale: bool,
+    // Merges neighbouring dmg values into wider bars when the distribution is too wide to draw
+    // a bar per value.
+    bin_pmf: bool,
+    // Zoom of the whole UI, fonts and plots alike.
+    ui_scale: f32,
+
+    // Extra ACs for which the full distribution is shown side by side.
+    pinned_acs: Vec<u8>,
+    new_pinned_ac: u8,
+
+    // Index of the build used in each round of the encounter.
+    encounter_rounds: Vec<usize>,
+    encounter_stats: Stats,
+    encounter_changed: bool,
+    encounter_rx: Receiver<Stats>,
+    encounter_tx: Sender<Vec<PMF>>,
+
+    // Pair of builds whose dmg difference is plotted.
+    difference_builds: (usize, usize),
+    // Source and destination picked for copying a build.
+    copy_builds: (usize, usize),
+    // Build that Ctrl+Z and Ctrl+Y act on.
+    last_edited: usize,
+    // AC and hit chance the AB calculator solves for.
+    ab_calculator: (u8, f64),
+
+    palette: Palette,
+    dark_mode: bool,
+}
+
+impl Default for Dnd {
+    fn default() -> Self {
+        let (encounter_tx, encounter_rx) = spawn_encounter_worker();
+        Self {
+            builds: vec![
+                BuildSlot::new(Build::default()),
+                BuildSlot::new(Build::default()),
+            ],
+
+            target: Target::default(),
+            desired_min_dmg: 15,
+            rounds: 1,
+            ac_range: (AC_MIN, AC_MAX),
+            percentile: 0.9,
+            monte_carlo: false,
+            log_scale: false,
+            bin_pmf: true,
+            ui_scale: 1.0,
+
+            pinned_acs: vec![13, 18],
+            new_pinned_ac: 15,
+
+            encounter_rounds: vec![0, 1, 1],
+            encounter_stats: Stats::default(),
+            encounter_changed: true,
+            encounter_rx,
+            encounter_tx,
+
+            difference_builds: (0, 1),
+            copy_builds: (0, 1),
+            last_edited: 0,
+            ab_calculator: (18, 0.65),
+
+            palette: Palette::default(),
+            dark_mode: true,
+        }
+    }
+}
+
+/// Convolving a long encounter gets slow, so just like the build stats it happens on its own
+/// thread. Shuts down once `Dnd` (and with it the sender) gets dropped.
+fn spawn_encounter_worker() -> (Sender<Vec<PMF>>, Receiver<Stats>) {
+    let (enque_tx, enque_rx) = mpsc::channel::<Vec<PMF>>();
+    let (stats_tx, stats_rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        loop {
+            match enque_rx.recv() {
+                Ok(rounds) => {
+                    let rounds: Vec<_> = rounds.iter().collect();
+                    if stats_tx.send(calc_encounter_stats(&rounds)).is_err() {
+                        return;
+                    }
+                }
+                Err(_) => return,
+            }
+        }
+    });
+    (enque_tx, stats_rx)
+}
+
+impl Dnd {
+    fn add_build(&mut self) {
+        if self.builds.len() < MAX_BUILDS {
+            let prev_or_def = self
+                .builds
+                .last()
+                .map(|slot| slot.build.clone())
+                .unwrap_or_default();
+            self.builds.push(BuildSlot::new(prev_or_def));
+        }
+    }
+
+    fn remove_build(&mut self, idx: usize) {
+        // There always has to be at least one build around.
+        if self.builds.len() <= 1 || idx >= self.builds.len() {
+            return;
+        }
+
+        self.builds.remove(idx);
+        self.encounter_rounds.retain(|&round| round != idx);
+        for round in &mut self.encounter_rounds {
+            if *round > idx {
+                *round -= 1;
+            }
+        }
+        self.encounter_changed = true;
+        self.update_outcomes();
+
+        self.clamp_build_indices();
+    }
+
+    /// Makes every stored build index valid again after builds went away.
+    fn clamp_build_indices(&mut self) {
+        let last = self.builds.len() - 1;
+        self.difference_builds.0 = self.difference_builds.0.min(last);
+        self.difference_builds.1 = self.difference_builds.1.min(last);
+        self.copy_builds.0 = self.copy_builds.0.min(last);
+        self.copy_builds.1 = self.copy_builds.1.min(last);
+        self.last_edited = self.last_edited.min(last);
+    }
+
+    /// Overwrites one build with another, the overwritten one can be undone.
+    fn copy_build(&mut self, from: usize, to: usize) {
+        if from == to || from >= self.builds.len() || to >= self.builds.len() {
+            return;
+        }
+
+        let build = self.builds[from].build.clone();
+        let slot = &mut self.builds[to];
+        slot.build = build;
+        slot.changed = true;
+    }
+
+    /// Swaps two builds, everything that refers to them by index follows them along.
+    fn swap_builds(&mut self, a: usize, b: usize) {
+        if a == b || a >= self.builds.len() || b >= self.builds.len() {
+            return;
+        }
+
+        // Slots carry their stats with them, only the indices into `builds` need fixing.
+        self.builds.swap(a, b);
+        let swap = |idx: &mut usize| {
+            if *idx == a {
+                *idx = b;
+            } else if *idx == b {
+                *idx = a;
+            }
+        };
+        for round in &mut self.encounter_rounds {
+            swap(round);
+        }
+        swap(&mut self.difference_builds.0);
+        swap(&mut self.difference_builds.1);
+        swap(&mut self.copy_builds.0);
+        swap(&mut self.copy_builds.1);
+        swap(&mut self.last_edited);
+        self.encounter_changed = true;
+        self.update_outcomes();
+    }
+
+    fn session(&self) -> Session {
+        Session {
+            builds: self.builds.iter().map(|slot| slot.build.clone()).collect(),
+            target: self.target,
+            desired_min_dmg: self.desired_min_dmg,
+            rounds: self.rounds,
+            ac_range: self.ac_range,
+            pinned_acs: self.pinned_acs.clone(),
+        }
+    }
+
+    /// Replaces all builds and settings, the builds start out with an empty history.
+    fn load_session(&mut self, session: Session) {
+        let mut builds = session.builds;
+        builds.truncate(MAX_BUILDS);
+        if builds.is_empty() {
+            builds.push(Build::default());
+        }
+        self.builds = builds.into_iter().map(BuildSlot::new).collect();
+        self.target = session.target;
+        self.desired_min_dmg = session.desired_min_dmg;
+        self.rounds = session.rounds.clamp(1, 20);
+        // Hand edited files can have the bounds the wrong way around, which leaves nothing to plot.
+        let (a, b) = session.ac_range;
+        self.ac_range = (a.min(b).min(AC_LIMIT), a.max(b).min(AC_LIMIT));
+        self.pinned_acs = session.pinned_acs;
+
+        let count = self.builds.len();
+        self.encounter_rounds.retain(|&round| round < count);
+        self.encounter_changed = true;
+        self.clamp_build_indices();
+    }
+
+    /// Mean dmg of every build as `(name, first AC, means)`, for the plot and its export.
+    fn all_means(&self) -> Vec<(String, u8, &[f64])> {
+        self.builds
+            .iter()
+            .enumerate()
+            .map(|(idx, slot)| (build_name(idx), slot.means_ac_min, slot.means.as_slice()))
+            .collect()
+    }
+
+    fn mark_all_changed(&mut self) {
+        for slot in &mut self.builds {
+            slot.changed = true;
+        }
+    }
+
+    fn update_outcomes(&mut self) {
+        let outcomes: Vec<Vec<_>> = self
+            .builds
+            .iter()
+            .map(|a| {
+                self.builds
+                    .iter()
+                    .map(|b| outcome_probabilities(&a.stats.pmf, &b.stats.pmf))
+                    .collect()
+            })
+            .collect();
+        let pmfs: Vec<_> = self.builds.iter().map(|slot| &slot.stats.pmf).collect();
+        let most_dmg = most_dmg_chances(&pmfs);
+        for ((slot, outcomes), most_dmg) in self.builds.iter_mut().zip(outcomes).zip(most_dmg) {
+            slot.outcomes = outcomes;
+            slot.most_dmg = most_dmg;
+        }
+    }
+}
+
+impl eframe::App for Dnd {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, SESSION_KEY, &self.session());
+        eframe::set_value(storage, DARK_MODE_KEY, &self.dark_mode);
+    }
+
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        for slot in &mut self.builds {
+            if slot.changed {
+                slot.changed = false;
+                slot.stats_tx
+                    .send(StatsRequest {
+                        build: slot.build.clone(),
+                        target: self.target,
+                        desired_min_dmg: self.desired_min_dmg,
+                        pinned_acs: self.pinned_acs.clone(),
+                        monte_carlo: self.monte_carlo,
+                        rounds: self.rounds,
+                    })
+                    .unwrap();
+                slot.means_tx
+                    .send((
+                        slot.build.clone(),
+                        self.target,
+                        self.ac_range.0..=self.ac_range.1,
+                    ))
+                    .unwrap();
+            }
+        }
+
+        let mut stats_changed = false;
+        for slot in &mut self.builds {
+            if let Ok(result) = slot.stats_rx.try_recv() {
+                slot.stats = result.stats;
+                slot.round_stats = result.round_stats;
+                slot.pinned_stats = result.pinned_stats;
+                slot.simulated_pmf = result.simulated_pmf;
+                slot.suggestion = result.suggestion;
+                stats_changed = true;
+            }
+
+            if let Ok((ac_min, means, std_devs, no_power_means, break_even)) =
+                slot.means_rx.try_recv()
+            {
+                slot.means_ac_min = ac_min;
+                slot.means = means;
+                slot.std_devs = std_devs;
+                slot.no_power_means = no_power_means;
+                slot.power_attack_break_even = break_even;
+            }
+        }
+
+        if stats_changed {
+            self.update_outcomes();
+            self.encounter_changed = true;
+        }
+
+        if self.encounter_changed {
+            self.encounter_changed = false;
+            let round_pmfs = self
+                .encounter_rounds
+                .iter()
+                .map(|&idx| self.builds[idx].round_stats.pmf.clone())
+                .collect();
+            self.encounter_tx.send(round_pmfs).unwrap();
+        }
+
+        if let Ok(stats) = self.encounter_rx.try_recv() {
+            self.encounter_stats = stats;
+        }
+
+        palette::show(ctx, self);
+
+        // Text fields have their own undo, so leave the shortcuts to them while typing.
+        if ctx.memory(|m| m.focused().is_none()) {
+            let (undo, redo) = ctx.input_mut(|i| {
+                (
+                    i.consume_key(Modifiers::COMMAND, Key::Z),
+                    i.consume_key(Modifiers::COMMAND, Key::Y)
+                        || i.consume_key(Modifiers::COMMAND | Modifiers::SHIFT, Key::Z),
+                )
+            });
+            if let Some(slot) = self.builds.get_mut(self.last_edited) {
+                if undo {
+                    slot.changed |= slot.history.undo(&mut slot.build);
+                }
+                if redo {
+                    slot.changed |= slot.history.redo(&mut slot.build);
+                }
+            }
+        }
+
+        if ctx.style().visuals.dark_mode != self.dark_mode {
+            ctx.set_visuals(if self.dark_mode {
+                egui::Visuals::dark()
+            } else {
+                egui::Visuals::light()
+            });
+        }
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                ui.label(RichText::new("DND build calculator").size(TITLE_SIZE));
+                ui.add_space(10.0);
+
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(
+                            self.builds.len() < MAX_BUILDS,
+                            egui::Button::new("Add build"),
+                        )
+                        .clicked()
+                    {
+                        self.add_build();
+                    }
+                    if ui
+                        .add_enabled(self.builds.len() > 1, egui::Button::new("Remove build"))
+                        .clicked()
+                    {
+                        self.remove_build(self.builds.len() - 1);
+                    }
+
+                    ui.add_space(20.0);
+                    let (from, to) = &mut self.copy_builds;
+                    ui.label("Copy");
+                    build_combo(ui, "copy_from", from, self.builds.len());
+                    ui.label("to");
+                    build_combo(ui, "copy_to", to, self.builds.len());
+                    let (from, to) = self.copy_builds;
+                    if ui
+                        .add_enabled(from != to, egui::Button::new("Copy"))
+                        .clicked()
+                    {
+                        self.copy_build(from, to);
+                    }
+
+                    ui.add_space(20.0);
+                    if ui.button("Save session").clicked() {
+                        save_json(&self.session(), "session.json", "session");
+                    }
+                    if ui.button("Load session").clicked() {
+                        if let Some(session) = load_json("session") {
+                            self.load_session(session);
+                        }
+                    }
+                    if ui.button("Export report").clicked() {
+                        export_report(&self.builds, &self.target, self.rounds);
+                    }
+
+                    ui.add_space(20.0);
+                    ui.menu_button("AB calculator", |ui| {
+                        let (ac, chance) = &mut self.ab_calculator;
+                        ui.horizontal(|ui| {
+                            ui.label("AC:");
+                            ui.add(egui::DragValue::new(ac));
+                            ui.label("Hit chance:");
+                            ui.add(egui::Slider::new(chance, 0.0..=1.0));
+                        });
+                        // Uses the target's rule for meeting the AC, but none of the builds.
+                        let target = Target {
+                            ac: *ac,
+                            ..self.target
+                        };
+                        match min_ab_for_hit_chance(*chance, &target) {
+                            Some(ab) if ab < *ac as i32 - 20 => {
+                                ui.label("Any AB, a natural 20 always hits")
+                            }
+                            Some(ab) => ui.label(format!("Needs an AB of at least {ab:+}")),
+                            None => ui.label("Out of reach, a natural 1 always misses"),
+                        };
+                    });
+
+                    ui.add_space(20.0);
+                    let theme = if self.dark_mode {
+                        "Light theme"
+                    } else {
+                        "Dark theme"
+                    };
+                    if ui.button(theme).clicked() {
+                        self.dark_mode = !self.dark_mode;
+                    }
+                });
+                ui.add_space(10.0);
+
+                let gap = 30.0;
+                let total_width = ui.max_rect().width() - 10.0;
+                let item_width = (total_width - gap) / 2.0;
+                let build_count = self.builds.len();
+                let frame = BuildFrame {
+                    item_width,
+                    desired_min_dmg: self.desired_min_dmg,
+                    target_ac: self.target.ac,
+                    target_hp: self.target.hp,
+                    rounds: self.rounds,
+                };
+                per_build_rows(ui, gap, build_count, |ui, idx| {
+                    build_box(ui, idx, &mut self.builds[idx], frame);
+                });
+
+                ui.add_space(20.0);
+                let mut target_changed = false;
+                ui.scope(|ui| {
+                    let style = ui.style_mut();
+                    for (_text_style, font_id) in style.text_styles.iter_mut() {
+                        font_id.size = SETTINGS_TEXT_SIZE;
+                    }
+
+                    ui.horizontal(|ui| {
+                        ui.label("Sim AC:");
+                        let changed = ui.add(egui::DragValue::new(&mut self.target.ac)).changed();
+                        target_changed |= changed;
+
+                        let prev_ac = self.target.ac;
+                        egui::ComboBox::from_id_salt("cr_preset")
+                            .selected_text("CR preset")
+                            .show_ui(ui, |ui| {
+                                for &(cr, ac) in presets::CR_ACS {
+                                    ui.selectable_value(
+                                        &mut self.target.ac,
+                                        ac,
+                                        format!("CR {cr} (AC {ac})"),
+                                    );
+                                }
+                            });
+                        target_changed |= prev_ac != self.target.ac;
+
+                        ui.add_space(10.0);
+                        let prev_comparison = self.target.ac_comparison;
+                        egui::ComboBox::from_label("to hit")
+                            .selected_text(self.target.ac_comparison.label())
+                            .show_ui(ui, |ui| {
+                                for comparison in
+                                    [AcComparison::MeetsOrBeats, AcComparison::MustExceed]
+                                {
+                                    ui.selectable_value(
+                                        &mut self.target.ac_comparison,
+                                        comparison,
+                                        comparison.label(),
+                                    );
+                                }
+                            });
+                        let changed = prev_comparison != self.target.ac_comparison;
+                        target_changed |= changed;
+
+                        ui.add_space(10.0);
+                        let prev_hit_roll = self.target.hit_roll;
+                        egui::ComboBox::from_id_salt("hit_roll")
+                            .selected_text(self.target.hit_roll.label())
+                            .show_ui(ui, |ui| {
+                                for hit_roll in HitRoll::ALL {
+                                    ui.selectable_value(
+                                        &mut self.target.hit_roll,
+                                        hit_roll,
+                                        hit_roll.label(),
+                                    );
+                                }
+                            })
+                            .response
+                            .on_hover_text(
+                                "Skips the attack rolls to show the raw dmg of every attack \
+                                 landing, whatever the AC.",
+                            );
+                        target_changed |= prev_hit_roll != self.target.hit_roll;
+
+                        ui.add_space(10.0);
+                        ui.label("Target save bonus:");
+                        let changed = ui
+                            .add(egui::DragValue::new(&mut self.target.save_bonus))
+                            .changed();
+                        target_changed |= changed;
+
+                        ui.add_space(10.0);
+                        // Both at once would just about cancel out, so only allow one.
+                        let changed = ui
+                            .checkbox(&mut self.target.resistance, "Resistant")
+                            .changed();
+                        if changed && self.target.resistance {
+                            self.target.vulnerable = false;
+                        }
+                        target_changed |= changed;
+
+                        ui.add_space(10.0);
+                        let changed = ui
+                            .checkbox(&mut self.target.vulnerable, "Vulnerable")
+                            .changed();
+                        if changed && self.target.vulnerable {
+                            self.target.resistance = false;
+                        }
+                        target_changed |= changed;
+
+                        ui.add_space(10.0);
+                        ui.label("DR:");
+                        let changed = ui.add(egui::DragValue::new(&mut self.target.dr)).changed();
+                        target_changed |= changed;
+
+                        ui.add_space(10.0);
+                        ui.label("Miss chance:");
+                        let changed = ui
+                            .add(egui::Slider::new(&mut self.target.miss_chance, 0.0..=1.0))
+                            .changed();
+                        target_changed |= changed;
+
+                        ui.add_space(10.0);
+                        ui.label("Min desired dmg:");
+                        let changed = ui
+                            .add(egui::DragValue::new(&mut self.desired_min_dmg))
+                            .changed();
+                        target_changed |= changed;
+
+                        ui.add_space(10.0);
+                        ui.label("Rounds:");
+                        let changed = ui
+                            .add(egui::DragValue::new(&mut self.rounds).range(1..=20))
+                            .changed();
+                        target_changed |= changed;
+
+                        ui.add_space(10.0);
+                        ui.label("Target HP:");
+                        let changed = ui.add(egui::DragValue::new(&mut self.target.hp)).changed();
+                        target_changed |= changed;
+                    });
+
+                    ui.horizontal(|ui| {
+                        // Each bound is limited by the other one so the range never ends up empty.
+                        let (ac_min, ac_max) = &mut self.ac_range;
+                        ui.label("Mean plot ACs:");
+                        let changed = ui
+                            .add(egui::DragValue::new(ac_min).range(0..=*ac_max))
+                            .changed();
+                        target_changed |= changed;
+                        ui.label("to");
+                        let changed = ui
+                            .add(egui::DragValue::new(ac_max).range(*ac_min..=AC_LIMIT))
+                            .changed();
+                        target_changed |= changed;
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.label("Percentile line:");
+                        ui.add(egui::Slider::new(&mut self.percentile, 0.0..=1.0));
+
+                        ui.add_space(10.0);
+                        let changed = ui
+                            .checkbox(&mut self.monte_carlo, "Monte Carlo (100k samples)")
+                            .changed();
+                        target_changed |= changed;
+
+                        ui.add_space(10.0);
+                        ui.checkbox(&mut self.log_scale, "Log scale distributions");
+                        ui.checkbox(&mut self.bin_pmf, "Bin wide distributions");
+
+                        ui.add_space(10.0);
+                        ui.label("UI scale:");
+                        let response = ui.add(egui::Slider::new(&mut self.ui_scale, 0.5..=2.5));
+                        // Zooming while dragging would move the slider away from the cursor,
+                        // so the new scale only kicks in once it's let go. Keyboard zoom
+                        // changes the scale as well, so otherwise follow whatever it is.
+                        if response.drag_stopped() || (response.changed() && !response.dragged()) {
+                            ui.ctx().set_zoom_factor(self.ui_scale);
+                        } else if !response.dragged() {
+                            self.ui_scale = ui.ctx().zoom_factor();
+                        }
+                    });
+
+                    egui::CollapsingHeader::new("Damage type defenses")
+                        .id_salt("defenses")
+                        .show(ui, |ui| {
+                            egui::Grid::new("defenses_grid").show(ui, |ui| {
+                                for (i, dmg_type) in DAMAGE_TYPES.iter().enumerate() {
+                                    ui.label(dmg_type.label());
+                                    let defense = &mut self.target.defenses[*dmg_type as usize];
+                                    let prev = *defense;
+                                    egui::ComboBox::from_id_salt(("defense", i))
+                                        .selected_text(defense.label())
+                                        .show_ui(ui, |ui| {
+                                            for option in [
+                                                Defense::Normal,
+                                                Defense::Resistant,
+                                                Defense::Immune,
+                                            ] {
+                                                ui.selectable_value(
+                                                    &mut *defense,
+                                                    option,
+                                                    option.label(),
+                                                );
+                                            }
+                                        });
+                                    let changed = prev != *defense;
+                                    target_changed |= changed;
+                                    if i % 4 == 3 {
+                                        ui.end_row();
+                                    }
+                                }
+                            });
+                        });
+
+                    ui.horizontal(|ui| {
+                        ui.label("Pinned ACs:");
+                        let mut remove_request = None;
+                        for (i, ac) in self.pinned_acs.iter().enumerate() {
+                            if ui
+                                .button(format!("{ac} x"))
+                                .on_hover_text("Unpin")
+                                .clicked()
+                            {
+                                remove_request = Some(i);
+                            }
+                        }
+                        ui.add(egui::DragValue::new(&mut self.new_pinned_ac));
+                        let add_request = ui.button("Pin").clicked()
+                            && !self.pinned_acs.contains(&self.new_pinned_ac);
+                        if add_request {
+                            self.pinned_acs.push(self.new_pinned_ac);
+                            self.pinned_acs.sort();
+                        }
+                        if let Some(idx) = remove_request {
+                            self.pinned_acs.remove(idx);
+                        }
+                        let changed = add_request || remove_request.is_some();
+                        target_changed |= changed;
+                    });
+
+                    // Big differences here point at a bug in either the exact math or
+                    // the simulation, so new mechanics get checked for free.
+                    egui::CollapsingHeader::new("Diagnostics")
+                        .id_salt("diagnostics")
+                        .show(ui, |ui| {
+                            if !self.monte_carlo {
+                                ui.label(
+                                    "Turn on Monte Carlo to compare it with the exact results",
+                                );
+                                return;
+                            }
+                            for (idx, slot) in self.builds.iter().enumerate() {
+                                let Some(simulated) = &slot.simulated_pmf else {
+                                    continue;
+                                };
+                                let (mean_diff, max_diff) =
+                                    pmf_discrepancy(&slot.stats.pmf, simulated);
+                                ui.label(format!(
+                                    "{}: means differ by {:.3}, largest chance difference {:.3}%",
+                                    build_name(idx),
+                                    mean_diff,
+                                    max_diff * 100.0
+                                ));
+                            }
+                        });
+                });
+                if target_changed {
+                    self.mark_all_changed();
+                }
+                ui.add_space(20.0);
+
+                let plot_width = (total_width - gap) / 2.0;
+                let plot_size = Vec2::new(plot_width, 500.0);
+                let pmf_view = PmfView {
+                    log_scale: self.log_scale,
+                    bin: self.bin_pmf,
+                    size: plot_size,
+                };
+                per_build_rows(ui, gap, build_count, |ui, idx| {
+                    let title = format!("Damage Distribution {}", build_letter(idx));
+                    let slot = &self.builds[idx];
+                    plot_pmf(
+                        ui,
+                        &title,
+                        &slot.stats.pmf,
+                        slot.simulated_pmf.as_ref(),
+                        pmf_view,
+                    );
+                });
+
+                ui.add_space(15.0);
+                per_build_rows(ui, gap, build_count, |ui, idx| {
+                    let title = format!("Cumulative Distribution {}", build_letter(idx));
+                    plot_cdf(
+                        ui,
+                        &title,
+                        &self.builds[idx].stats,
+                        self.percentile,
+                        plot_size,
+                    );
+                });
+
+                ui.add_space(15.0);
+                per_build_rows(ui, gap, build_count, |ui, idx| {
+                    let title = format!("Chance to Deal at Least {}", build_letter(idx));
+                    plot_survival(
+                        ui,
+                        &title,
+                        &self.builds[idx].stats.pmf,
+                        self.desired_min_dmg,
+                        plot_size,
+                    );
+                });
+
+                if !self.pinned_acs.is_empty() {
+                    ui.add_space(15.0);
+                    per_build_rows(ui, gap, build_count, |ui, idx| {
+                        let title =
+                            format!("Damage Distribution at pinned ACs {}", build_letter(idx));
+                        plot_pinned(
+                            ui,
+                            &title,
+                            &self.pinned_acs,
+                            &self.builds[idx].pinned_stats,
+                            plot_size,
+                        );
+                    });
+                }
+
+                per_build_rows(ui, gap, build_count, |ui, idx| {
+                    let title = format!("Mean DMG for given AC for {}", build_name(idx));
+                    let slot = &self.builds[idx];
+                    // Means of the other builds can lag behind or cover different ACs
+                    // while they are being recomputed, those are skipped until they line up.
+                    let others: Vec<_> = self
+                        .builds
+                        .iter()
+                        .enumerate()
+                        .filter(|&(other_idx, other)| {
+                            other_idx != idx
+                                && other.means_ac_min == slot.means_ac_min
+                                && other.means.len() == slot.means.len()
+                        })
+                        .collect();
+                    let crossovers: Vec<_> = others
+                        .iter()
+                        .flat_map(|&(other_idx, other)| {
+                            crossover_acs(&slot.means, &other.means, slot.means_ac_min)
+                                .into_iter()
+                                .map(move |ac| (other_idx, ac))
+                        })
+                        .collect();
+                    ui.vertical(|ui| {
+                        plot_mean_for_ac(
+                            ui,
+                            &title,
+                            plot_size,
+                            MeanSeries {
+                                means: &slot.means,
+                                std_devs: &slot.std_devs,
+                                no_power_means: slot.no_power_means.as_deref(),
+                                ac_min: slot.means_ac_min,
+                            },
+                            &crossovers,
+                        );
+
+                        // Alone there is nothing to be ahead of, so the build is tuned for
+                        // the ACs right before its dmg falls off.
+                        let other_means: Vec<_> = others
+                            .iter()
+                            .map(|(_, other)| other.means.as_slice())
+                            .collect();
+                        let text = if build_count == 1 {
+                            steepest_drop_ac(&slot.means, slot.means_ac_min).map(|(ac, drop)| {
+                                format!("Tuned up to AC {ac}, the mean drops by {drop:.2} after it")
+                            })
+                        } else {
+                            best_margin_ac(&slot.means, &other_means, slot.means_ac_min).map(
+                                |(ac, margin)| {
+                                    if margin > 0.0 {
+                                        format!(
+                                            "Tuned for AC {ac}, {margin:.2} mean dmg ahead of \
+                                             the other builds"
+                                        )
+                                    } else {
+                                        format!(
+                                            "Never ahead of the other builds, closest at AC \
+                                             {ac} ({margin:.2})"
+                                        )
+                                    }
+                                },
+                            )
+                        };
+                        if let Some(text) = text {
+                            ui.label(RichText::new(text).size(STAT_TEXT_SIZE));
+                        }
+                    });
+                });
+
+                ui.add_space(15.0);
+                let all_means = self.all_means();
+                if ui.button("Export AC table").clicked() {
+                    export_ac_table(&all_means);
+                }
+                plot_means_comparison(
+                    ui,
+                    "Mean DMG for given AC",
+                    Vec2::new(total_width, 500.0),
+                    &all_means,
+                );
+
+                ui.add_space(15.0);
+                ui.horizontal(|ui| {
+                    ui.label("Difference between");
+                    let (a, b) = &mut self.difference_builds;
+                    build_combo(ui, "difference_a", a, build_count);
+                    ui.label("and");
+                    build_combo(ui, "difference_b", b, build_count);
+                });
+                let (a, b) = self.difference_builds;
+                let title = format!(
+                    "Damage Difference {} - {}",
+                    build_letter(a),
+                    build_letter(b)
+                );
+                plot_difference(
+                    ui,
+                    &title,
+                    &difference_pmf(&self.builds[a].stats.pmf, &self.builds[b].stats.pmf),
+                    plot_size,
+                );
+
+                ui.add_space(15.0);
+                comparison_table(ui, &self.builds);
+
+                ui.separator();
+                let round_stats: Vec<_> =
+                    self.builds.iter().map(|slot| &slot.round_stats).collect();
+                encounter_box(
+                    ui,
+                    &mut self.encounter_rounds,
+                    &round_stats,
+                    &self.encounter_stats,
+                    &mut self.encounter_changed,
+                    pmf_view,
+                );
+            });
+        });
+
+        // Wait for drags and edits to finish, so each of them becomes a single undo step.
+        let editing = ctx.input(|i| i.pointer.any_down()) || ctx.memory(|m| m.focused().is_some());
+        if !editing {
+            for (idx, slot) in self.builds.iter_mut().enumerate() {
+                if slot.history.record(&slot.build) {
+                    self.last_edited = idx;
+                }
+            }
+        }
+    }
+}
+
+/// Lays out one widget per build, two builds per row.
+fn per_build_rows(ui: &mut Ui, gap: f32, build_count: usize, mut add: impl FnMut(&mut Ui, usize)) {
+    for row_start in (0..build_count).step_by(2) {
+        ui.horizontal(|ui| {
+            ui.spacing_mut().item_spacing.x = gap;
+            for idx in row_start..build_count.min(row_start + 2) {
+                add(ui, idx);
+            }
+        });
+    }
+}
+
+/// Everything a build box shows that is the same for all of the builds in a frame.
+#[derive(Clone, Copy)]
+struct BuildFrame {
+    item_width: f32,
+    desired_min_dmg: u32,
+    target_ac: u8,
+    target_hp: u32,
+    // Number of rounds the displayed distributions cover.
+    rounds: u32,
+}
+
+fn build_box(ui: &mut Ui, build_idx: usize, slot: &mut BuildSlot, frame: BuildFrame) {
+    let build_name = &build_name(build_idx);
+    let BuildSlot {
+        build,
+        history,
+        stats,
+        outcomes,
+        most_dmg,
+        suggestion,
+        power_attack_break_even,
+        changed,
+        ..
+    } = slot;
+
+    let style = ui.style_mut();
+    for (_text_style, font_id) in style.text_styles.iter_mut() {
+        font_id.size = BUILD_TEXT_SIZE;
+    }
+    ui.vertical(|ui| {
+        ui.group(|ui| {
+            ui.set_width(frame.item_width);
+            ui.set_min_height(370.0);
+
+            ui.horizontal(|ui| {
+                ui.label(RichText::new(build_name).size(HEADING_SIZE));
+                // Names don't affect the stats so there is no need to flag a change.
+                ui.add(
+                    egui::TextEdit::singleline(&mut build.name)
+                        .hint_text("Name")
+                        .desired_width(200.0),
+                );
+            });
+            build_toolbar(ui, build_idx, build, history, stats, changed);
+
+            let mut duplicate_request = None;
+            let mut remove_request = None;
+            for (i, attack) in build.attacks.iter_mut().enumerate() {
+                // Without an id of their own the widget ids of a row would depend on how many
+                // widgets the rows above it have, so focus would jump around whenever one of
+                // them changes shape. Tab goes through the rows in the order they are shown.
+                ui.push_id(i, |ui| {
+                    match attack_header_row(ui, build_name, i, attack, changed) {
+                        Some(AttackRequest::Duplicate) => duplicate_request = Some(i),
+                        Some(AttackRequest::Remove) => remove_request = Some(i),
+                        None => {}
+                    }
+                    let total_mean = stats.attack_means.get(i).copied();
+                    let break_even = power_attack_break_even.get(i).copied().flatten();
+                    attack_roll_row(ui, build_name, i, attack, total_mean, break_even, changed);
+                    attack_dmg_type_rows(ui, build_name, i, attack, changed);
+                });
+            }
+            if let Some(idx) = duplicate_request {
+                build.attacks.insert(idx + 1, build.attacks[idx].clone());
+                *changed = true;
+            }
+            if let Some(idx) = remove_request {
+                build.attacks.remove(idx);
+                *changed = true;
+            }
+            build_options(ui, build_name, build, changed);
+
+            ui.add_space(10.0);
+            build_stats(ui, build_name, build, stats, suggestion.as_ref(), frame);
+            ui.add_space(10.0);
+            build_outcomes(ui, build_idx, outcomes, *most_dmg, stats, frame);
+        });
+    });
+}
+
+/// Buttons acting on the build as a whole.
+fn build_toolbar(
+    ui: &mut Ui,
+    build_idx: usize,
+    build: &mut Build,
+    history: &mut EditHistory,
+    stats: &Stats,
+    changed: &mut bool,
+) {
+    ui.horizontal(|ui| {
+        if ui.button("Add attack").clicked() {
+            build.add_attack();
+            *changed = true;
+        }
+
+        if ui
+            .add_enabled(history.can_undo(build), egui::Button::new("Undo"))
+            .clicked()
+        {
+            *changed |= history.undo(build);
+        }
+        if ui
+            .add_enabled(history.can_redo(build), egui::Button::new("Redo"))
+            .clicked()
+        {
+            *changed |= history.redo(build);
+        }
+
+        // Clearing and resetting throw away the whole build so ask before doing it.
+        let id = egui::Id::new(build_name(build_idx));
+        if confirm_button(ui, clear_confirm_id(build_idx), "Clear", "Clear build?") {
+            *build = Build::empty();
+            *changed = true;
+        }
+        if confirm_button(ui, id.with("confirm_reset"), "Reset", "Reset build?") {
+            *build = Build::default();
+            *changed = true;
+        }
+
+        if ui.button("Save build").clicked() {
+            save_build(build);
+        }
+        if ui.button("Load build").clicked() {
+            *changed |= load_build(build);
+        }
+        if ui.button("Export CSV").clicked() {
+            export_csv(stats);
+        }
+    });
+}
+
+/// Asked for from an attack row, carried out once all of the rows are drawn.
+enum AttackRequest {
+    Duplicate,
+    Remove,
+}
+
+/// Name, attack bonus and dmg dice of an attack.
+fn attack_header_row(
+    ui: &mut Ui,
+    build_name: &str,
+    i: usize,
+    attack: &mut Attack,
+    changed: &mut bool,
+) -> Option<AttackRequest> {
+    let mut request = None;
+    ui.horizontal(|ui| {
+        ui.spacing_mut().item_spacing = egui::vec2(6.0, 0.0);
+        let hint = attack.label(i);
+        ui.add(
+            egui::TextEdit::singleline(&mut attack.name)
+                .hint_text(hint)
+                .desired_width(100.0),
+        );
+        ui.label("AB:")
+            .on_hover_text("Added to the d20 attack roll");
+        *changed |= committed(&ui.add(egui::DragValue::new(&mut attack.ab)));
+        // Keeps the dmg dice apart from the attack roll, so a D20 among them
+        // doesn't read as the d20 rolled to hit.
+        ui.separator();
+
+        ui.label("Flat dmg:");
+        *changed |= committed(&ui.add(egui::DragValue::new(&mut attack.flat)));
+
+        ui.label("Dmg dice")
+            .on_hover_text("Rolled for dmg on a hit and doubled on a crit");
+        for (die, count, _) in &mut attack.dice {
+            ui.label(format!("D{}:", *die as u32));
+            *changed |= committed(&ui.add(egui::DragValue::new(count)));
+        }
+
+        ui.label("Min face:")
+            .on_hover_text("Lower rolls on the dmg dice count as this, e.g. Elemental Adept");
+        *changed |= committed(&ui.add(egui::DragValue::new(&mut attack.min_face).range(1..=20)));
+
+        ui.menu_button("Import", |ui| {
+            let id = ui.make_persistent_id((build_name, i, "import"));
+            let (mut text, mut error) = ui
+                .data_mut(|d| d.get_temp::<(String, Option<String>)>(id))
+                .unwrap_or_default();
+            ui.add(
+                egui::TextEdit::singleline(&mut text)
+                    .hint_text("1d8+4 slashing, +7 to hit")
+                    .desired_width(250.0),
+            );
+            if ui.button("Parse").clicked() {
+                match parse::parse_attack(&text) {
+                    Ok(parsed) => {
+                        let name = std::mem::take(&mut attack.name);
+                        *attack = Attack { name, ..parsed };
+                        *changed = true;
+                        text.clear();
+                        error = None;
+                        ui.close_menu();
+                    }
+                    Err(err) => error = Some(err),
+                }
+            }
+            if let Some(error) = &error {
+                ui.colored_label(Color32::LIGHT_RED, error);
+            }
+            ui.data_mut(|d| d.insert_temp(id, (text, error)));
+        });
+        if ui.button("Duplicate").clicked() {
+            request = Some(AttackRequest::Duplicate);
+        }
+        if ui.button("Remove").clicked() {
+            request = Some(AttackRequest::Remove);
+        }
+    });
+    request
+}
+
+/// How the attack is rolled, or the saving throw it forces instead. `total_mean` is the mean
+/// dmg of the attack over all of its targets and `break_even` the AC from which its -5/+10
+/// stops paying off.
+fn attack_roll_row(
+    ui: &mut Ui,
+    build_name: &str,
+    i: usize,
+    attack: &mut Attack,
+    total_mean: Option<f64>,
+    break_even: Option<u8>,
+    changed: &mut bool,
+) {
+    ui.horizontal(|ui| {
+        ui.spacing_mut().item_spacing = egui::vec2(6.0, 0.0);
+        let mut is_save = attack.save.is_some();
+        if ui.checkbox(&mut is_save, "Saving throw").changed() {
+            attack.save = is_save.then(Save::default);
+            *changed = true;
+        }
+
+        if let Some(save) = &mut attack.save {
+            ui.label("DC:");
+            *changed |= committed(&ui.add(egui::DragValue::new(&mut save.dc)));
+            *changed |= ui
+                .checkbox(&mut save.half_on_success, "Half on success")
+                .changed();
+            *changed |= ui
+                .checkbox(&mut save.no_damage_on_crit_save, "None on nat 20")
+                .changed();
+            ui.label("Targets:");
+            *changed |= committed(&ui.add(egui::DragValue::new(&mut save.targets).range(1..=20)));
+            if let Some(mean) = total_mean.filter(|_| save.targets > 1) {
+                ui.label(format!("Total: {mean:.2}"))
+                    .on_hover_text("Expected dmg over all of the targets");
+            }
+            return;
+        }
+
+        let prev_roll_mode = attack.roll_mode;
+        egui::ComboBox::from_id_salt((build_name, i, "roll_mode"))
+            .selected_text(attack.roll_mode.label())
+            .show_ui(ui, |ui| {
+                let is_advantage = matches!(attack.roll_mode, RollMode::Advantage { .. });
+                if ui
+                    .selectable_label(attack.roll_mode == RollMode::Normal, "Normal")
+                    .clicked()
+                {
+                    attack.roll_mode = RollMode::Normal;
+                }
+                if ui.selectable_label(is_advantage, "Advantage").clicked() && !is_advantage {
+                    attack.roll_mode = RollMode::Advantage { dice: 2 };
+                }
+                if ui
+                    .selectable_label(attack.roll_mode == RollMode::Disadvantage, "Disadvantage")
+                    .clicked()
+                {
+                    attack.roll_mode = RollMode::Disadvantage;
+                }
+            });
+        if let RollMode::Advantage { dice } = &mut attack.roll_mode {
+            ui.label("d20s:");
+            ui.add(egui::DragValue::new(dice).range(2..=3));
+        }
+        *changed |= prev_roll_mode != attack.roll_mode;
+
+        let prev_sources = attack.roll_sources;
+        ui.menu_button("Situation", |ui| {
+            ui.label("Advantage:");
+            for (label, field) in RollSources::ADVANTAGE {
+                ui.checkbox(field(&mut attack.roll_sources), label);
+            }
+            ui.label("Disadvantage:");
+            for (label, field) in RollSources::DISADVANTAGE {
+                ui.checkbox(field(&mut attack.roll_sources), label);
+            }
+        });
+        *changed |= prev_sources != attack.roll_sources;
+        let effective = attack.effective_roll_mode();
+        if effective != attack.roll_mode {
+            ui.label(format!("Rolls: {}", effective.label()));
+        }
+
+        *changed |= ui
+            .checkbox(&mut attack.power_attack, "-5/+10")
+            .on_hover_text("Sharpshooter / Great Weapon Master")
+            .changed();
+        if let Some(ac) = break_even {
+            ui.label(format!("(worth it below AC {ac})"))
+                .on_hover_text("From this AC on the attack deals at least as much without -5/+10");
+        }
+
+        ui.label("Crit on:");
+        *changed |= committed(&ui.add(egui::DragValue::new(&mut attack.crit_range).range(2..=20)));
+        egui::ComboBox::from_id_salt((build_name, i, "crit_multiplier"))
+            .selected_text(attack.crit_multiplier.label())
+            .show_ui(ui, |ui| {
+                for crit_multiplier in CritMultiplier::ALL {
+                    *changed |= ui
+                        .selectable_value(
+                            &mut attack.crit_multiplier,
+                            crit_multiplier,
+                            crit_multiplier.label(),
+                        )
+                        .changed();
+                }
+            });
+        ui.label("+");
+        let response = ui
+            .add(egui::DragValue::new(&mut attack.crit_flat_bonus))
+            .on_hover_text("Flat dmg only dealt on a crit");
+        *changed |= committed(&response);
+
+        *changed |= ui
+            .checkbox(&mut attack.reroll_on_miss, "Reroll miss")
+            .on_hover_text("Attack once more if this attack misses")
+            .changed();
+
+        *changed |= ui
+            .checkbox(&mut attack.reroll_lowest, "Reroll lowest die")
+            .on_hover_text("Roll the lowest dmg die again and keep the new roll")
+            .changed();
+        if attack.reroll_lowest && attack.reroll_lowest_pool().is_none() {
+            ui.colored_label(Color32::LIGHT_RED, "(needs dice of one size and type)");
+        }
+
+        *changed |= ui
+            .checkbox(&mut attack.explode, "Exploding dice")
+            .on_hover_text(format!(
+                "A die rolling its max is rolled again and added, at most \
+                 {EXPLODE_LEVELS} extra times"
+            ))
+            .changed();
+
+        *changed |= ui
+            .checkbox(&mut attack.roll_twice_sum, "Roll dmg twice")
+            .on_hover_text("Roll the dmg dice twice and add both rolls together")
+            .changed();
+        if attack.roll_twice_sum {
+            *changed |= ui
+                .checkbox(&mut attack.roll_twice_flat, "Flat twice")
+                .on_hover_text("Add the flat dmg to both rolls instead of once")
+                .changed();
+        }
+
+        *changed |= ui
+            .checkbox(&mut attack.on_crit_only, "Only after crit")
+            .on_hover_text("Bonus attack, only made if an earlier attack crit this turn")
+            .changed();
+
+        *changed |= ui
+            .checkbox(&mut attack.nova, "Nova")
+            .on_hover_text("Once per rest, only used in the first round")
+            .changed();
+
+        let mut overridden = attack.hit_override.is_some();
+        if ui
+            .checkbox(&mut overridden, "Fixed hit chance")
+            .on_hover_text("Ignore the attack bonus and the AC")
+            .changed()
+        {
+            attack.hit_override = overridden.then_some(Chance(0.5));
+            *changed = true;
+        }
+        if let Some(Chance(chance)) = &mut attack.hit_override {
+            *changed |= committed(&ui.add(egui::Slider::new(chance, 0.0..=1.0)));
+        }
+    });
+}
+
+/// Dmg types of the attack, along with its dice of any size.
+fn attack_dmg_type_rows(
+    ui: &mut Ui,
+    build_name: &str,
+    i: usize,
+    attack: &mut Attack,
+    changed: &mut bool,
+) {
+    ui.horizontal(|ui| {
+        ui.spacing_mut().item_spacing = egui::vec2(6.0, 0.0);
+        ui.label("Flat type:");
+        *changed |= damage_type_combo(ui, (build_name, i, "flat"), &mut attack.flat_type);
+        for (die, count, dmg_type) in attack.dice.iter_mut() {
+            if *count == 0 {
+                continue;
+            }
+            ui.label(format!("{:?} type:", die));
+            *changed |= damage_type_combo(ui, (build_name, i, *die as u8), dmg_type);
+        }
+    });
+    ui.horizontal(|ui| {
+        ui.spacing_mut().item_spacing = egui::vec2(6.0, 0.0);
+        let mut remove_custom = None;
+        for (j, (sides, count, dmg_type)) in attack.custom_dice.iter_mut().enumerate() {
+            ui.label("Sides:");
+            *changed |= committed(&ui.add(egui::DragValue::new(sides).range(1..=100)));
+            ui.label("Count:");
+            *changed |= committed(&ui.add(egui::DragValue::new(count)));
+            *changed |= damage_type_combo(ui, (build_name, i, "custom", j), dmg_type);
+            if ui.small_button("x").on_hover_text("Remove die").clicked() {
+                remove_custom = Some(j);
+            }
+        }
+        if let Some(j) = remove_custom {
+            attack.custom_dice.remove(j);
+            *changed = true;
+        }
+        if ui.button("Add custom die").clicked() {
+            attack.custom_dice.push((3, 1, attack.flat_type));
+            *changed = true;
+        }
+    });
+}
+
+/// Everything about the build that isn't tied to a single attack.
+fn build_options(ui: &mut Ui, build_name: &str, build: &mut Build, changed: &mut bool) {
+    *changed |= ui
+        .checkbox(&mut build.crit_enabled, "Crits Enabled")
+        .changed();
+    ui.horizontal(|ui| {
+        *changed |= ui.checkbox(&mut build.savage, "Savage Attacker").changed();
+        if build.savage {
+            *changed |= ui
+                .checkbox(&mut build.savage_per_die, "Reroll each die")
+                .on_hover_text("Keep the better roll of every die instead of the better total")
+                .changed();
+        }
+    });
+    ui.horizontal(|ui| {
+        *changed |= ui.checkbox(&mut build.bless, "Bless").changed();
+        *changed |= ui
+            .checkbox(&mut build.bane, "Bane")
+            .on_hover_text("Subtract a d4 from every attack roll")
+            .changed();
+    });
+    *changed |= ui
+        .checkbox(&mut build.lucky, "Lucky")
+        .on_hover_text("Reroll natural 1s on attack rolls")
+        .changed();
+    ui.horizontal(|ui| {
+        ui.spacing_mut().item_spacing = egui::vec2(6.0, 0.0);
+        ui.label("Once per turn:")
+            .on_hover_text("Sneak Attack and similar, added to the first attack that hits");
+        for (die, count) in &mut build.sneak_dice {
+            ui.label(format!("D{}:", *die as u32));
+            *changed |= committed(&ui.add(egui::DragValue::new(count)));
+        }
+        ui.label("Flat:");
+        *changed |= committed(&ui.add(egui::DragValue::new(&mut build.first_hit_bonus)));
+    });
+    ui.horizontal(|ui| {
+        ui.spacing_mut().item_spacing = egui::vec2(6.0, 0.0);
+        // Only feeds the dmg per action label, nothing has to be recomputed.
+        ui.label("Actions per round:")
+            .on_hover_text("Bonus actions count as well");
+        ui.add(egui::DragValue::new(&mut build.actions).range(1..=10));
+    });
+    // Notes don't affect the stats so there is no need to flag a change.
+    egui::CollapsingHeader::new("Notes")
+        .id_salt((build_name, "notes"))
+        .default_open(!build.notes.is_empty())
+        .show(ui, |ui| {
+            ui.add(
+                egui::TextEdit::multiline(&mut build.notes)
+                    .hint_text("Gear, level, party context...")
+                    .desired_width(f32::INFINITY)
+                    .desired_rows(3),
+            );
+        });
+}
 
-                ui.horizontal(|ui| {
-                    ui.spacing_mut().item_spacing.x = gap;
-                    let plot_size = Vec2::new(plot_width, 500.0);
-                    plot_mean_for_ac(
-                        ui,
-                        "Mean DMG for given AC for Build A",
-                        plot_size,
-                        &self.means_a,
-                    );
-                    plot_mean_for_ac(
-                        ui,
-                        "Mean DMG for given AC for Build B",
-                        plot_size,
-                        &self.means_b,
-                    );
-                });
+/// A single line of the stats under a build, explained on hover.
+fn stat_label(ui: &mut Ui, text: impl Into<String>, hover: impl Into<egui::WidgetText>) {
+    ui.label(RichText::new(text).size(STAT_TEXT_SIZE))
+        .on_hover_text(hover);
+}
 
-                ui.separator();
+fn build_stats(
+    ui: &mut Ui,
+    build_name: &str,
+    build: &Build,
+    stats: &Stats,
+    suggestion: Option<&(String, f64)>,
+    frame: BuildFrame,
+) {
+    let BuildFrame {
+        target_ac, rounds, ..
+    } = frame;
+    if rounds > 1 {
+        ui.label(
+            RichText::new(format!(
+                "Over {rounds} rounds, the distribution gets wider with every round."
+            ))
+            .size(STAT_TEXT_SIZE),
+        );
+    }
+    stat_label(
+        ui,
+        format!("Mean damage: {:.2}", stats.mean),
+        format!("Average total dmg against AC {target_ac}, misses count as 0"),
+    );
+    stat_label(
+        ui,
+        format!(
+            "Damage per action: {:.2}",
+            stats.mean / (build.actions.max(1) * rounds) as f64
+        ),
+        "Mean dmg of a round split over the actions it takes",
+    );
+    if let Some((description, gain)) = suggestion {
+        stat_label(
+            ui,
+            format!("Best upgrade: {description} (+{gain:.2} per round)"),
+            "Out of one more die on an attack, one more attack, +1 to hit and Savage Attacker, \
+             Bless or Lucky, the one adding the most mean dmg at the target's AC",
+        );
+    }
+    if let Some(sustained) = &stats.sustained {
+        stat_label(
+            ui,
+            format!(
+                "Sustained round: mean {:.2}, standard deviation {:.2}",
+                sustained.mean, sustained.std_dev
+            ),
+            "A single round after the nova attacks are used up",
+        );
+    }
+    stat_label(
+        ui,
+        format!("Standard deviation: {:.2}", stats.std_dev),
+        "How far the dmg typically lands from the mean",
+    );
+    stat_label(
+        ui,
+        format!(
+            "Variance: {:.2}, coefficient of variation: {:.2}",
+            stats.variance, stats.coefficient_of_variation
+        ),
+        "Variance is the standard deviation squared. The coefficient of variation is the \
+         standard deviation divided by the mean, lower means more consistent dmg",
+    );
+    stat_label(
+        ui,
+        format!("Skewness: {:.2}", stats.skewness),
+        "Positive means a long tail of big hits",
+    );
+    stat_label(
+        ui,
+        format!("Median: {}, mode: {}", stats.median, stats.mode),
+        "Half of the time the dmg is at most the median. The mode is the single most likely \
+         dmg, often 0 when attacks miss a lot",
+    );
+    stat_label(
+        ui,
+        format!(
+            "Damage range: {}-{}, lowest on a hit: {}",
+            stats.min_damage, stats.max_damage, stats.min_hit_damage
+        ),
+        "Lowest and highest possible dmg. The lowest on a hit leaves out the 0 from missing \
+         everything",
+    );
+    stat_label(
+        ui,
+        format!(
+            "Expected hits: {:.2}, crits: {:.2}, chance to hit at least once: {:.1}%",
+            stats.expected_hits,
+            stats.expected_crits,
+            stats.any_hit_chance * 100.0
+        ),
+        format!(
+            "Average number of attacks that hit (crits included) and that crit against \
+             AC {target_ac}. Saving throw attacks don't count"
+        ),
+    );
+    stat_label(
+        ui,
+        format!(
+            "Chance to crit at least once: {:.1}%, dmg added by crits: {:.2}",
+            stats.any_crit_chance * 100.0,
+            stats.crit_uplift
+        ),
+        "The added dmg is the mean minus the mean if crits dealt regular hit dmg. Once per \
+         turn dice and Savage Attacker are left out",
+    );
+    stat_label(
+        ui,
+        format!(
+            "Chance to deal more than the mean: {:.1}%",
+            stats.above_mean_chance * 100.0
+        ),
+        "Chance of a result strictly above the mean dmg",
+    );
+    stat_label(
+        ui,
+        format!(
+            "Chance to land within one standard deviation of the mean: {:.1}%",
+            stats.within_std_dev_chance * 100.0
+        ),
+        "Chance the dmg lands between the mean minus and plus one standard deviation. Low \
+         values mean the dmg is spread out, e.g. by big crits",
+    );
+
+    // Stats lag behind edits a bit, so only show the breakdown once they match up.
+    if build.attacks.len() > 1 && stats.attack_means.len() == build.attacks.len() {
+        egui::CollapsingHeader::new("Damage per attack")
+            .id_salt((build_name, "attack_means"))
+            .show(ui, |ui| {
+                for (i, (attack, mean)) in build.attacks.iter().zip(&stats.attack_means).enumerate()
+                {
+                    let share = if stats.mean > 0.0 {
+                        mean / stats.mean * 100.0
+                    } else {
+                        0.0
+                    };
+                    ui.label(format!("{}: {:.2} ({:.0}%)", attack.label(i), mean, share));
+                }
+            });
+    }
+    if !stats.hit_counts.is_empty() {
+        egui::CollapsingHeader::new("Number of hits")
+            .id_salt((build_name, "hit_counts"))
+            .show(ui, |ui| {
+                plot_hit_counts(ui, &format!("{build_name} hits"), &stats.hit_counts);
+            });
+    }
+    if stats.mean > 0.0 {
+        egui::CollapsingHeader::new("Damage sources")
+            .id_salt((build_name, "contributions"))
+            .show(ui, |ui| {
+                plot_contributions(
+                    ui,
+                    &format!("{build_name} damage sources"),
+                    &stats.contributions,
+                );
             });
-        });
     }
 }
 
-fn build_box(
+/// How the build fares against the other builds and the target.
+fn build_outcomes(
     ui: &mut Ui,
-    item_width: f32,
-    build_name: &str,
-    build: &mut Build,
+    build_idx: usize,
+    outcomes: &[(f64, f64, f64)],
+    most_dmg: (f64, f64),
     stats: &Stats,
-    desired_min_dmg: u32,
-    changed: &mut bool,
+    frame: BuildFrame,
 ) {
-    let style = ui.style_mut();
-    for (_text_style, font_id) in style.text_styles.iter_mut() {
-        font_id.size = 18.0;
+    let BuildFrame {
+        desired_min_dmg,
+        target_ac,
+        target_hp,
+        ..
+    } = frame;
+    let build_name = build_name(build_idx);
+    for (other_idx, (win, tie, lose)) in outcomes.iter().enumerate() {
+        if other_idx == build_idx {
+            continue;
+        }
+        let other_name = self::build_name(other_idx);
+        ui.label(format!(
+            "Against {other_name}: {build_name} wins {:.1}%, tie {:.1}%, {other_name} wins {:.1}%.",
+            win * 100.0,
+            tie * 100.0,
+            lose * 100.0,
+        ))
+        .on_hover_text(format!(
+            "Chances that one build deals more dmg than the other against AC {target_ac}, \
+             with both of them rolled independently"
+        ));
+    }
+    if outcomes.len() > 2 {
+        ui.label(format!(
+            "Chance to deal the most damage: {:.1}%, tied for the most: {:.1}%.",
+            most_dmg.0 * 100.0,
+            most_dmg.1 * 100.0,
+        ))
+        .on_hover_text(
+            "Chance to deal strictly more than every other build at once, and the chance to \
+             share the top spot with some of them",
+        );
     }
-    ui.vertical(|ui| {
-        ui.group(|ui| {
-            ui.set_width(item_width);
-            ui.set_height(370.0);
 
-            ui.label(RichText::new(build_name).size(24.0));
-            if ui.button("Add attack").clicked() {
-                let prev_or_def = build.attacks.last().cloned().unwrap_or(Attack::default());
-                build.attacks.push(prev_or_def);
-                *changed = true;
-            }
-            let mut remove_request = None;
-            for (i, attack) in build.attacks.iter_mut().enumerate() {
-                ui.horizontal(|ui| {
-                    ui.spacing_mut().item_spacing = egui::vec2(6.0, 0.0);
-                    ui.label("AB:");
-                    *changed |= ui.add(egui::DragValue::new(&mut attack.ab)).changed();
+    ui.label(format!(
+        "There is {:.1}% chance to deal at least {} damage.",
+        stats.min_dmg_chance * 100.0,
+        desired_min_dmg,
+    ))
+    .on_hover_text(format!(
+        "Chance the total dmg against AC {target_ac} reaches {desired_min_dmg}, the min \
+         desired dmg"
+    ));
+    ui.label(format!(
+        "There is {:.1}% chance to drop a target with {} HP, with {:.1} overkill on average.",
+        stats.chance_to_kill * 100.0,
+        target_hp,
+        stats.expected_overkill,
+    ))
+    .on_hover_text(
+        "Chance the total dmg reaches the target's HP. Overkill is the average dmg past the HP, \
+         only counting the times the target drops",
+    );
+}
 
-                    ui.label("Flat dmg:");
-                    *changed |= ui.add(egui::DragValue::new(&mut attack.flat)).changed();
+/// Whether the value of a widget changed. Values typed into a field also count as changed
+/// once Enter is pressed, even if they ended up the same, so Enter always recomputes.
+fn committed(response: &egui::Response) -> bool {
+    response.changed()
+        || (response.lost_focus() && response.ctx.input(|i| i.key_pressed(Key::Enter)))
+}
 
-                    ui.label("D4:");
-                    *changed |= ui
-                        .add(egui::DragValue::new(&mut attack.dice[0].1))
-                        .changed();
+/// State of the confirmation behind a build's Clear button. The command palette sets it to
+/// ask from the build itself instead of clearing right away.
+fn clear_confirm_id(build_idx: usize) -> egui::Id {
+    egui::Id::new(build_name(build_idx)).with("confirm_clear")
+}
 
-                    ui.label("D6:");
-                    *changed |= ui
-                        .add(egui::DragValue::new(&mut attack.dice[1].1))
-                        .changed();
+/// Button that asks for confirmation before acting, returns true once confirmed.
+fn confirm_button(ui: &mut Ui, id: egui::Id, text: &str, question: &str) -> bool {
+    let mut confirming = ui.data_mut(|d| d.get_temp::<bool>(id).unwrap_or(false));
+    let mut confirmed = false;
+    if confirming {
+        ui.label(question);
+        if ui.button("Yes").clicked() {
+            confirmed = true;
+            confirming = false;
+        }
+        if ui.button("No").clicked() {
+            confirming = false;
+        }
+    } else if ui.button(text).clicked() {
+        confirming = true;
+    }
+    ui.data_mut(|d| d.insert_temp(id, confirming));
+    confirmed
+}
 
-                    ui.label("D8:");
-                    *changed |= ui
-                        .add(egui::DragValue::new(&mut attack.dice[2].1))
-                        .changed();
+fn build_combo(ui: &mut Ui, id_salt: impl std::hash::Hash, idx: &mut usize, build_count: usize) {
+    egui::ComboBox::from_id_salt(id_salt)
+        .selected_text(build_name(*idx))
+        .show_ui(ui, |ui| {
+            for other in 0..build_count {
+                ui.selectable_value(&mut *idx, other, build_name(other));
+            }
+        });
+}
 
-                    ui.label("D10:");
-                    *changed |= ui
-                        .add(egui::DragValue::new(&mut attack.dice[3].1))
-                        .changed();
+fn damage_type_combo(ui: &mut Ui, id_salt: impl std::hash::Hash, value: &mut DamageType) -> bool {
+    let prev = *value;
+    egui::ComboBox::from_id_salt(id_salt)
+        .width(100.0)
+        .selected_text(value.label())
+        .show_ui(ui, |ui| {
+            for dmg_type in DAMAGE_TYPES {
+                ui.selectable_value(&mut *value, dmg_type, dmg_type.label());
+            }
+        });
+    prev != *value
+}
 
-                    ui.label("D20:");
-                    *changed |= ui
-                        .add(egui::DragValue::new(&mut attack.dice[4].1))
-                        .changed();
+/// The key numbers of every build side by side, one column per build.
+fn comparison_table(ui: &mut Ui, builds: &[BuildSlot]) {
+    type Row = (&'static str, fn(&BuildSlot) -> String);
+    let rows: [Row; 6] = [
+        ("Mean", |slot| format!("{:.2}", slot.stats.mean)),
+        ("Median", |slot| slot.stats.median.to_string()),
+        ("Standard deviation", |slot| {
+            format!("{:.2}", slot.stats.std_dev)
+        }),
+        ("Min desired dmg chance", |slot| {
+            format!("{:.1}%", slot.stats.min_dmg_chance * 100.0)
+        }),
+        ("Kill chance", |slot| {
+            format!("{:.1}%", slot.stats.chance_to_kill * 100.0)
+        }),
+        ("Deals the most dmg", |slot| {
+            format!("{:.1}%", slot.most_dmg.0 * 100.0)
+        }),
+    ];
+
+    ui.label(RichText::new("Comparison").size(HEADING_SIZE));
+    TableBuilder::new(ui)
+        .striped(true)
+        .vscroll(false)
+        .column(Column::auto().at_least(200.0))
+        .columns(Column::auto().at_least(100.0), builds.len())
+        .header(24.0, |mut header| {
+            header.col(|_| {});
+            for idx in 0..builds.len() {
+                header.col(|ui| {
+                    ui.strong(build_name(idx));
+                });
+            }
+        })
+        .body(|mut body| {
+            for (name, value) in rows {
+                body.row(22.0, |mut row| {
+                    row.col(|ui| {
+                        ui.label(name);
+                    });
+                    for slot in builds {
+                        row.col(|ui| {
+                            ui.label(value(slot));
+                        });
+                    }
+                });
+            }
+        });
+}
+
+fn encounter_box(
+    ui: &mut Ui,
+    rounds: &mut Vec<usize>,
+    round_stats: &[&Stats],
+    encounter_stats: &Stats,
+    changed: &mut bool,
+    pmf_view: PmfView,
+) {
+    egui::CollapsingHeader::new(RichText::new("Encounter").size(HEADING_SIZE))
+        .id_salt("encounter")
+        .show(ui, |ui| {
+            ui.label(
+                "Pick the build used in each round, e.g. a nova build for the first round \
+                 and a sustained one for the rest.",
+            );
+            if ui.button("Add round").clicked() {
+                rounds.push(rounds.last().copied().unwrap_or(0));
+                *changed = true;
+            }
 
+            let mut remove_request = None;
+            for (i, build_idx) in rounds.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(format!("Round {}:", i + 1));
+                    let prev = *build_idx;
+                    egui::ComboBox::from_id_salt(("encounter_round", i))
+                        .selected_text(build_name(*build_idx))
+                        .show_ui(ui, |ui| {
+                            for idx in 0..round_stats.len() {
+                                ui.selectable_value(&mut *build_idx, idx, build_name(idx));
+                            }
+                        });
+                    *changed |= prev != *build_idx;
+                    ui.label(format!("Mean damage: {:.2}", round_stats[*build_idx].mean));
                     if ui.button("Remove").clicked() {
                         remove_request = Some(i);
                     }
                 });
             }
             if let Some(idx) = remove_request {
-                build.attacks.remove(idx);
+                rounds.remove(idx);
                 *changed = true;
             }
-            *changed |= ui
-                .checkbox(&mut build.crit_enabled, "Crits Enabled")
-                .changed();
-            *changed |= ui.checkbox(&mut build.savage, "Savage Attacker").changed();
-            ui.add_space(10.0);
-            ui.label(RichText::new(format!("Mean damage: {:.2}", stats.mean)).size(15.0));
-            ui.label(RichText::new(format!("Standard deviation: {:.2}", stats.std_dev)).size(15.0));
+
             ui.add_space(10.0);
             ui.label(format!(
-                "There is {:.1}% chance that {} will out damage the other build.",
-                stats.greater_then_chance * 100.0,
-                build_name
+                "Total mean damage over {} rounds: {:.2}",
+                rounds.len(),
+                encounter_stats.mean
             ));
+            ui.label(format!(
+                "Standard deviation: {:.2}",
+                encounter_stats.std_dev
+            ));
+            plot_pmf(
+                ui,
+                "Encounter Damage Distribution",
+                &encounter_stats.pmf,
+                None,
+                pmf_view,
+            );
+        });
+
+    // The encounter is sent off to its worker at the start of the next frame.
+    if *changed {
+        ui.ctx().request_repaint();
+    }
+}
+
+/// Draws the plot title along with a button that brings the plot back to its default view.
+/// Returns whether the button was clicked.
+fn plot_header(ui: &mut Ui, title: &str) -> bool {
+    ui.horizontal(|ui| {
+        ui.label(RichText::new(title).size(PLOT_TITLE_SIZE).strong());
+        ui.small_button("Reset view").clicked()
+    })
+    .inner
+}
 
-            ui.label(RichText::new(format!(
-                "There is {:.1}% chance to deal at least {} damage.",
-                stats.min_dmg_chance * 100.0,
-                desired_min_dmg,
-            )));
+/// Plot colors are picked for the dark theme and most of them are too pale to see on a light
+/// background, so they get darkened there.
+fn contrast(ui: &Ui, color: Color32) -> Color32 {
+    if ui.visuals().dark_mode {
+        return color;
+    }
+    let [r, g, b, a] = color.to_array();
+    Color32::from_rgba_premultiplied(r / 2, g / 2, b / 2, a)
+}
+
+/// Chance to land exactly that many attacks, handy for on hit effects.
+fn plot_hit_counts(ui: &mut Ui, title: &str, hit_counts: &PMF) {
+    let bars: Vec<Bar> = hit_counts
+        .iter()
+        .map(|(&hits, &prob)| {
+            Bar::new(hits as f64, prob)
+                .name(format!("{hits} hits: {:.1}%", prob * 100.0))
+                .fill(Color32::from_rgb(70, 53, 177))
+                .stroke(Stroke::new(0.1, contrast(ui, Color32::WHITE)))
+        })
+        .collect();
+
+    Plot::new(title)
+        .height(120.0)
+        .x_axis_label("hits")
+        .y_axis_label("chance")
+        .allow_scroll(false)
+        .allow_boxed_zoom(false)
+        .allow_drag(false)
+        .show(ui, |plot_ui| {
+            plot_ui.bar_chart(BarChart::new(title, bars).width(0.8));
+        });
+}
+
+fn die_color(sides: u32) -> Color32 {
+    match sides {
+        4 => Color32::from_rgb(86, 180, 233),
+        6 => Color32::from_rgb(0, 158, 115),
+        8 => Color32::from_rgb(240, 228, 66),
+        10 => Color32::from_rgb(213, 94, 0),
+        20 => Color32::from_rgb(204, 121, 167),
+        _ => Color32::GRAY,
+    }
+}
+
+/// Single bar of the mean dmg, split into every die size, the flat bonus and the extra crit
+/// dice stacked on top of each other.
+fn plot_contributions(ui: &mut Ui, title: &str, contributions: &Contributions) {
+    let parts = contributions
+        .dice
+        .iter()
+        .map(|(&sides, &mean)| (format!("d{sides}"), mean, die_color(sides)))
+        .chain([
+            (
+                "Flat".to_string(),
+                contributions.flat,
+                contrast(ui, Color32::WHITE),
+            ),
+            ("Crit dice".to_string(), contributions.crit, Color32::RED),
+        ]);
+
+    let mut offset = 0.0;
+    let mut charts = Vec::new();
+    for (name, mean, color) in parts {
+        if mean <= 0.0 {
+            continue;
+        }
+        let bar = Bar::new(0.0, mean)
+            .base_offset(offset)
+            .name(format!("{name}: {mean:.2}"))
+            .fill(color);
+        charts.push(BarChart::new(name, vec![bar]).color(color).horizontal());
+        offset += mean;
+    }
+
+    Plot::new(title)
+        .height(80.0)
+        .x_axis_label("mean dmg")
+        .show_y(false)
+        .show_axes([true, false])
+        .allow_scroll(false)
+        .allow_boxed_zoom(false)
+        .allow_drag(false)
+        .legend(Legend::default().position(Corner::RightBottom))
+        .show(ui, |plot_ui| {
+            for chart in charts {
+                plot_ui.bar_chart(chart);
+            }
         });
-    });
 }
 
-fn plot_pmf(ui: &mut Ui, title: &str, pmf: &PMF, size: Vec2) {
+/// How the dmg distributions get drawn, the same for every one of them.
+#[derive(Clone, Copy)]
+struct PmfView {
+    log_scale: bool,
+    // Merges neighbouring dmg values into wider bars when there are too many to draw.
+    bin: bool,
+    size: Vec2,
+}
+
+/// `simulated` gets drawn as a line on top of the exact bars.
+fn plot_pmf(ui: &mut Ui, title: &str, pmf: &PMF, simulated: Option<&PMF>, view: PmfView) {
+    let PmfView {
+        log_scale,
+        bin,
+        size,
+    } = view;
+    // A bar per dmg value gets thinner than a pixel on wide distributions, so those are
+    // merged into buckets of equal width.
+    let max_bars = (size.x / 4.0).max(1.0) as u32;
+    let range = match (pmf.first_key_value(), pmf.last_key_value()) {
+        (Some((&min, _)), Some((&max, _))) => max - min + 1,
+        _ => 0,
+    };
+    let bucket = if bin && range > max_bars {
+        range.div_ceil(max_bars)
+    } else {
+        1
+    };
+    let binned = bin_pmf(pmf, bucket);
+    let simulated = simulated.map(|simulated| bin_pmf(simulated, bucket));
+    let pmf = &binned;
+    // Bars are as wide as the smallest gap between drawn values, so sparse distributions
+    // (like a single big die) don't turn into a row of thin lines.
+    let width = pmf
+        .keys()
+        .zip(pmf.keys().skip(1))
+        .map(|(a, b)| b - a)
+        .min()
+        .unwrap_or(bucket);
+    let center = |dmg: u32| dmg as f64 + (bucket - 1) as f64 / 2.0;
+
+    // log10 of a chance is negative, so in log scale the bars grow up from the smallest
+    // value instead of from 0.
+    let to_y = |prob: f64| if log_scale { prob.log10() } else { prob };
+    let base = if log_scale {
+        pmf.values()
+            .filter(|&&prob| prob > 0.0)
+            .map(|&prob| to_y(prob).floor())
+            .fold(0.0, f64::min)
+    } else {
+        0.0
+    };
     let bars: Vec<Bar> = pmf
         .iter()
+        .filter(|&(_, &prob)| !log_scale || prob > 0.0)
         .map(|(&dmg, &prob)| {
-            Bar::new(dmg as f64, prob)
+            Bar::new(center(dmg), to_y(prob) - base)
+                .base_offset(base)
                 .fill(Color32::from_rgb(70, 53, 177))
-                .stroke(Stroke::new(0.1, Color32::WHITE))
+                .stroke(Stroke::new(0.1, contrast(ui, Color32::WHITE)))
         })
         .collect();
-    let chart = BarChart::new(title, bars.clone()).width(1.0);
+    let chart = BarChart::new(title, bars.clone()).width(width as f64);
+    let simulated_color = contrast(ui, Color32::YELLOW);
+    ui.allocate_ui(size, |ui| {
+        ui.vertical(|ui| {
+            let reset = plot_header(ui, title);
+            let mut plot = Plot::new(title)
+                .view_aspect(2.0)
+                .x_axis_label(if bucket > 1 {
+                    format!("dmg (bins of {bucket})")
+                } else {
+                    "dmg".to_string()
+                })
+                .y_axis_label(if log_scale { "log10 chance" } else { "chance" })
+                .allow_scroll(false)
+                .allow_boxed_zoom(false)
+                .allow_drag(false)
+                .cursor_color(Color32::TRANSPARENT);
+            if reset {
+                plot = plot.reset();
+            }
+            plot.show(ui, |plot_ui| {
+                plot_ui.bar_chart(chart);
+                if let Some(simulated) = &simulated {
+                    let points: PlotPoints = simulated
+                        .iter()
+                        .filter(|&(_, &prob)| prob > 0.0)
+                        .map(|(&dmg, &prob)| [center(dmg), to_y(prob)])
+                        .collect();
+                    plot_ui.line(
+                        Line::new(format!("{title} Monte Carlo"), points)
+                            .color(simulated_color)
+                            .width(2.0),
+                    );
+                }
+            });
+        });
+    });
+}
+
+/// Sums the chances of every `bucket` consecutive dmg values, keyed by the lowest one.
+fn bin_pmf(pmf: &PMF, bucket: u32) -> PMF {
+    let mut binned = PMF::new();
+    for (&dmg, &prob) in pmf {
+        *binned.entry(dmg / bucket * bucket).or_insert(0.0) += prob;
+    }
+    binned
+}
+
+/// Bars right of zero are outcomes where the first build deals more dmg.
+fn plot_difference(ui: &mut Ui, title: &str, diff: &DiffPMF, size: Vec2) {
+    let bars: Vec<Bar> = diff
+        .iter()
+        .map(|(&margin, &prob)| {
+            let fill = match margin {
+                m if m > 0 => Color32::from_rgb(53, 160, 90),
+                m if m < 0 => Color32::from_rgb(190, 60, 60),
+                _ => Color32::GRAY,
+            };
+            Bar::new(margin as f64, prob)
+                .fill(fill)
+                .stroke(Stroke::new(0.1, contrast(ui, Color32::WHITE)))
+        })
+        .collect();
+    let chart = BarChart::new(title, bars).width(1.0);
+    ui.allocate_ui(size, |ui| {
+        ui.vertical(|ui| {
+            let reset = plot_header(ui, title);
+            let mut plot = Plot::new(title)
+                .view_aspect(2.0)
+                .x_axis_label("dmg difference")
+                .y_axis_label("chance")
+                .allow_scroll(false)
+                .allow_boxed_zoom(false)
+                .allow_drag(false)
+                .cursor_color(Color32::TRANSPARENT);
+            if reset {
+                plot = plot.reset();
+            }
+            plot.show(ui, |plot_ui| {
+                plot_ui.bar_chart(chart);
+                plot_ui.vline(VLine::new(format!("{title} zero"), 0.0).color(Color32::GRAY));
+            });
+        });
+    });
+}
+
+fn plot_pinned(ui: &mut Ui, title: &str, pinned_acs: &[u8], pinned_stats: &[Stats], size: Vec2) {
+    // Overlapping bar charts are unreadable so every AC gets its own line instead.
+    let lines: Vec<Line> = pinned_acs
+        .iter()
+        .zip(pinned_stats)
+        .map(|(ac, stats)| {
+            let points: Vec<[f64; 2]> = stats
+                .pmf
+                .iter()
+                .map(|(&dmg, &prob)| [dmg as f64, prob])
+                .collect();
+            Line::new(format!("AC {ac}"), points).width(2.0)
+        })
+        .collect();
+
     ui.allocate_ui(size, |ui| {
         ui.vertical(|ui| {
-            ui.label(RichText::new(title).size(20.0).strong());
-            Plot::new(title)
+            let reset = plot_header(ui, title);
+            let mut plot = Plot::new(title)
                 .view_aspect(2.0)
                 .x_axis_label("dmg")
                 .y_axis_label("chance")
                 .allow_scroll(false)
                 .allow_boxed_zoom(false)
                 .allow_drag(false)
-                .cursor_color(Color32::TRANSPARENT)
-                .show(ui, |plot_ui| {
-                    plot_ui.bar_chart(chart);
-                });
+                .legend(Legend::default().position(Corner::RightTop));
+            if reset {
+                plot = plot.reset();
+            }
+            plot.show(ui, |plot_ui| {
+                for line in lines {
+                    plot_ui.line(line);
+                }
+            });
         });
     });
 }
 
-fn plot_cdf(ui: &mut Ui, title: &str, cdf: &CDF, size: Vec2) {
-    fn to_step_points(cdf: &CDF) -> PlotPoints {
+fn plot_cdf(ui: &mut Ui, title: &str, stats: &Stats, percentile: f64, size: Vec2) {
+    /// Staircase through the cdf entries, starting and ending right on the first and last one.
+    fn steps(cdf: &CDF) -> Vec<[f64; 2]> {
+        // Float accumulation can push the last few entries slightly above 1.
+        let cdf: Vec<_> = cdf.iter().map(|&(x, y)| (x as f64, y.min(1.0))).collect();
+
         let mut points = Vec::new();
-        if cdf.is_empty() {
-            return points.into();
+        if let Some(&(x_first, y_first)) = cdf.first() {
+            points.push([x_first, y_first]);
         }
-
-        points.push([cdf[0].0 as f64, 0.0]);
-
         for window in cdf.windows(2) {
-            let (x1, y1) = window[0];
-            let (x2, _) = window[1];
-            points.push([x1 as f64, y1]);
-            points.push([x2 as f64, y1]);
+            let (_, y1) = window[0];
+            let (x2, y2) = window[1];
+            points.push([x2, y1]);
+            points.push([x2, y2]);
         }
+        points
+    }
 
-        if let Some(&(x_last, y_last)) = cdf.last() {
-            points.push([x_last as f64, y_last]);
-        }
+    fn to_step_points(cdf: &CDF) -> PlotPoints {
+        let mut points = steps(cdf);
+        let (Some(&[x_first, _]), Some(&[x_last, y_last])) = (points.first(), points.last()) else {
+            return points.into();
+        };
+
+        // Start a step early so the first riser (usually the miss spike at 0 dmg) is visible
+        // instead of hiding on the y axis.
+        points.splice(0..0, [[x_first - 1.0, 0.0], [x_first, 0.0]]);
+
+        // Same on the other end, which also keeps a build that always misses as a flat
+        // line at 1 rather than a single point.
+        points.push([x_last + 1.0, y_last]);
 
         points.into()
     }
 
+    let cdf = &stats.cdf;
     let points: PlotPoints = to_step_points(cdf);
     let line = Line::new(title, points)
         .color(Color32::from_rgb(200, 100, 100))
         .name("Cumulative Distribution")
         .fill_alpha(0.0)
-        .stroke(egui::Stroke::new(5.0, Color32::LIGHT_BLUE));
+        .stroke(egui::Stroke::new(5.0, contrast(ui, Color32::LIGHT_BLUE)));
 
-    let p95_x = cdf
+    let [p25_x, p75_x, p95_x] = stats.percentiles([0.25, 0.75, 0.95]).map(|x| x as f64);
+
+    // Shades the area under the curve between the quartiles. Unlike the curve itself the band
+    // isn't padded, so it stops right at the quartile lines.
+    let iqr: Vec<_> = cdf
         .iter()
-        .find(|&&(_, p)| p >= 0.95)
-        .map(|&(x, _)| x as f64)
-        .unwrap_or(0.0);
+        .copied()
+        .filter(|&(x, _)| (x as f64) >= p25_x && (x as f64) <= p75_x)
+        .collect();
+    let iqr_band = Line::new("Interquartile range", PlotPoints::from(steps(&iqr)))
+        .color(contrast(ui, Color32::LIGHT_BLUE))
+        .fill(0.0)
+        .fill_alpha(0.2)
+        .width(0.0);
 
     let vline_95 = VLine::new("95", p95_x)
         .color(Color32::RED)
         .name("95th percentile");
 
-    let p25_x = cdf
-        .iter()
-        .find(|&&(_, p)| p >= 0.25)
-        .map(|&(x, _)| x as f64)
-        .unwrap_or(0.0);
-
     let vline_25 = VLine::new("25", p25_x)
         .color(Color32::GREEN)
         .name("25th percentile");
 
-    let p75_x = cdf
-        .iter()
-        .find(|&&(_, p)| p >= 0.75)
-        .map(|&(x, _)| x as f64)
-        .unwrap_or(0.0);
-
     let vline_75 = VLine::new("75", p75_x)
         .color(Color32::ORANGE)
         .name("75th percentile");
 
+    let vline_custom = VLine::new("custom", stats.percentile(percentile) as f64)
+        .color(contrast(ui, Color32::YELLOW))
+        .name(format!("{:.0}th percentile", percentile * 100.0));
+
     ui.allocate_ui(size, |ui| {
         ui.vertical(|ui| {
-            ui.label(RichText::new(title).size(20.0).strong());
-            Plot::new(title)
+            let reset = plot_header(ui, title);
+            let mut plot = Plot::new(title)
                 .view_aspect(2.0)
                 .x_axis_label("dmg")
                 .y_axis_label("cumulative probability")
@@ -508,34 +3236,173 @@ fn plot_cdf(ui: &mut Ui, title: &str, cdf: &CDF, size: Vec2) {
                 .allow_drag(false)
                 .auto_bounds([true, true])
                 .default_y_bounds(0.0, 1.1)
-                .legend(Legend::default().position(Corner::RightBottom))
-                .show(ui, |plot_ui| {
-                    plot_ui.line(line);
-                    plot_ui.vline(vline_25);
-                    plot_ui.vline(vline_75);
-                    plot_ui.vline(vline_95);
-                });
+                .legend(Legend::default().position(Corner::RightBottom));
+            if reset {
+                plot = plot.reset();
+            }
+            plot.show(ui, |plot_ui| {
+                plot_ui.line(iqr_band);
+                plot_ui.line(line);
+                plot_ui.vline(vline_25);
+                plot_ui.vline(vline_75);
+                plot_ui.vline(vline_95);
+                plot_ui.vline(vline_custom);
+            });
+        });
+    });
+}
+
+/// Chance to deal at least a given amount of dmg, with a marker at the desired minimum.
+fn plot_survival(ui: &mut Ui, title: &str, pmf: &PMF, desired_min_dmg: u32, size: Vec2) {
+    let points: PlotPoints = survival(pmf)
+        .into_iter()
+        .map(|(dmg, chance)| [dmg as f64, chance])
+        .collect();
+    let line = Line::new("Chance to deal at least", points)
+        .color(contrast(ui, Color32::LIGHT_BLUE))
+        .width(2.0);
+    let vline_min =
+        VLine::new("Min desired dmg", desired_min_dmg as f64).color(contrast(ui, Color32::YELLOW));
+
+    ui.allocate_ui(size, |ui| {
+        ui.vertical(|ui| {
+            let reset = plot_header(ui, title);
+            let mut plot = Plot::new(title)
+                .view_aspect(2.0)
+                .x_axis_label("dmg")
+                .y_axis_label("chance")
+                .allow_scroll(false)
+                .allow_boxed_zoom(false)
+                .allow_drag(false)
+                .default_y_bounds(0.0, 1.1)
+                .legend(Legend::default().position(Corner::RightTop));
+            if reset {
+                plot = plot.reset();
+            }
+            plot.show(ui, |plot_ui| {
+                plot_ui.line(line);
+                plot_ui.vline(vline_min);
+            });
+        });
+    });
+}
+
+/// Mean dmg of every build as lines on a single plot, as `(name, first AC, means)`.
+fn plot_means_comparison(ui: &mut Ui, title: &str, size: Vec2, means: &[(String, u8, &[f64])]) {
+    ui.allocate_ui(size, |ui| {
+        ui.vertical(|ui| {
+            let reset = plot_header(ui, title);
+            let mut plot = Plot::new(title)
+                .view_aspect(4.0)
+                .x_axis_label("AC")
+                .y_axis_label("dmg")
+                .allow_scroll(false)
+                .allow_boxed_zoom(false)
+                .allow_drag(false)
+                .legend(Legend::default().position(Corner::RightTop));
+            if reset {
+                plot = plot.reset();
+            }
+            plot.show(ui, |plot_ui| {
+                for (idx, (name, ac_min, means)) in means.iter().enumerate() {
+                    let points: PlotPoints = means
+                        .iter()
+                        .enumerate()
+                        .map(|(offset, mean)| [(*ac_min as usize + offset) as f64, *mean])
+                        .collect();
+                    plot_ui.line(
+                        Line::new(name.as_str(), points)
+                            .color(BUILD_COLORS[idx % BUILD_COLORS.len()])
+                            .width(2.0),
+                    );
+                }
+            });
         });
     });
 }
 
-fn plot_mean_for_ac(ui: &mut Ui, title: &str, size: Vec2, means: &Vec<f64>) {
+/// Mean dmg of a build for every AC starting at `ac_min`, along with what gets drawn around it.
+struct MeanSeries<'a> {
+    means: &'a [f64],
+    std_devs: &'a [f64],
+    no_power_means: Option<&'a [f64]>,
+    ac_min: u8,
+}
+
+/// When `no_power_means` is given both variants are also drawn as lines, so it's easy
+/// to spot the AC where power attacking stops paying off. `crossovers` are the ACs where
+/// another build's mean crosses this one, along with the index of that build.
+fn plot_mean_for_ac(
+    ui: &mut Ui,
+    title: &str,
+    size: Vec2,
+    series: MeanSeries,
+    crossovers: &[(usize, f64)],
+) {
+    let MeanSeries {
+        means,
+        std_devs,
+        no_power_means,
+        ac_min,
+    } = series;
+    fn to_line(name: &str, ac_min: u8, means: &[f64]) -> Line {
+        let points: PlotPoints = means
+            .iter()
+            .enumerate()
+            .map(|(offset, mean)| [(ac_min as usize + offset) as f64, *mean])
+            .collect();
+        Line::new(name, points).width(2.0)
+    }
+
     let bars: Vec<Bar> = means
         .iter()
         .enumerate()
         .map(|(offset, mean)| {
-            let ac = AC_MIN + offset as u8;
+            let ac = ac_min + offset as u8;
             Bar::new(ac as f64, *mean)
                 .fill(Color32::from_rgb(70, 53, 177))
                 .stroke(Stroke::new(0.2, Color32::BLACK))
         })
         .collect();
 
+    // Only label every bar when there is enough room for the text, otherwise the
+    // exact values are still available on hover.
+    let show_labels = !means.is_empty() && size.x / means.len() as f32 >= 40.0;
+    let labels: Vec<Text> = means
+        .iter()
+        .enumerate()
+        .map(|(offset, mean)| {
+            let ac = ac_min + offset as u8;
+            Text::new(
+                format!("{title} label {ac}"),
+                PlotPoint::new(ac as f64, *mean),
+                format!("{:.1}", mean),
+            )
+            .anchor(Align2::CENTER_BOTTOM)
+        })
+        .collect();
+
+    // Error bars of one std dev around every mean, they all share a name so the legend
+    // only lists them once.
+    let error_bars: Vec<Line> = means
+        .iter()
+        .zip(std_devs)
+        .enumerate()
+        .map(|(offset, (mean, std_dev))| {
+            let ac = (ac_min as usize + offset) as f64;
+            let points = vec![[ac, (mean - std_dev).max(0.0)], [ac, mean + std_dev]];
+            Line::new("±1 std dev", points)
+                .color(contrast(ui, Color32::LIGHT_GRAY))
+                .width(2.0)
+        })
+        .collect();
+
     let chart = BarChart::new(title, bars.clone()).width(1.0);
+    let crossover_color = contrast(ui, Color32::LIGHT_RED);
     ui.allocate_ui(size, |ui| {
         ui.vertical(|ui| {
-            ui.label(RichText::new(title).size(20.0).strong());
-            Plot::new(title)
+            let reset = plot_header(ui, title);
+            let mut plot = Plot::new(title)
                 .view_aspect(2.0)
                 .x_axis_label("AC")
                 .y_axis_label("dmg")
@@ -543,9 +3410,152 @@ fn plot_mean_for_ac(ui: &mut Ui, title: &str, size: Vec2, means: &Vec<f64>) {
                 .allow_boxed_zoom(false)
                 .allow_drag(false)
                 .cursor_color(Color32::TRANSPARENT)
-                .show(ui, |plot_ui| {
-                    plot_ui.bar_chart(chart);
+                .legend(Legend::default().position(Corner::RightTop))
+                .label_formatter(|_name, value| {
+                    let ac = value.x.round();
+                    let offset = ac - ac_min as f64;
+                    match (means.get(offset as usize), std_devs.get(offset as usize)) {
+                        (Some(mean), Some(std_dev)) if offset >= 0.0 => {
+                            format!("AC {ac}: {mean:.2} ± {std_dev:.2} dmg")
+                        }
+                        (Some(mean), None) if offset >= 0.0 => format!("AC {ac}: {mean:.2} dmg"),
+                        _ => String::new(),
+                    }
                 });
+            if reset {
+                plot = plot.reset();
+            }
+            plot.show(ui, |plot_ui| {
+                plot_ui.bar_chart(chart);
+                for error_bar in error_bars {
+                    plot_ui.line(error_bar);
+                }
+                if let Some(no_power_means) = no_power_means {
+                    plot_ui.line(to_line("Power attack", ac_min, means));
+                    plot_ui.line(to_line("No power attack", ac_min, no_power_means));
+                }
+                if show_labels {
+                    for label in labels {
+                        plot_ui.text(label);
+                    }
+                }
+                let top = means.iter().copied().fold(0.0, f64::max);
+                for &(other_idx, ac) in crossovers {
+                    let name = format!("Crosses {} at AC {ac:.1}", build_name(other_idx));
+                    plot_ui.vline(VLine::new(name.clone(), ac).color(crossover_color));
+                    plot_ui.text(
+                        Text::new(name, PlotPoint::new(ac, top), format!("AC {ac:.1}"))
+                            .color(crossover_color)
+                            .anchor(Align2::LEFT_TOP),
+                    );
+                }
+            });
         });
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_builds_load_back_unchanged() {
+        let mut build = Build {
+            name: "Paladin".to_string(),
+            savage: true,
+            lucky: true,
+            notes: "Smites on crits".to_string(),
+            ..Build::default()
+        };
+        build.add_attack();
+        let attack = &mut build.attacks[1];
+        attack.ab = 9;
+        attack.dice[0] = (Die::D8, 2, DamageType::Radiant);
+        attack.custom_dice.push((12, 1, DamageType::Slashing));
+        attack.save = Some(Save::default());
+        attack.hit_override = Some(Chance(0.65));
+
+        let json = serde_json::to_string(&build).unwrap();
+        let loaded: Build = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded, build);
+    }
+
+    #[test]
+    fn dice_are_saved_as_their_sides() {
+        assert_eq!(serde_json::to_string(&Die::D8).unwrap(), "8");
+        assert_eq!(serde_json::from_str::<Die>("10").unwrap(), Die::D10);
+        assert!(serde_json::from_str::<Die>("7").is_err());
+    }
+
+    #[test]
+    fn advantage_and_disadvantage_cancel_out() {
+        let mut attack = Attack {
+            roll_sources: RollSources {
+                flanking: true,
+                long_range: true,
+                ..RollSources::default()
+            },
+            ..Attack::default()
+        };
+        assert_eq!(attack.effective_roll_mode(), RollMode::Normal);
+        // Even Elven Accuracy cancels out, however many sources there are.
+        attack.roll_mode = RollMode::Advantage { dice: 3 };
+        attack.roll_sources.hidden = true;
+        assert_eq!(attack.effective_roll_mode(), RollMode::Normal);
+
+        attack.roll_sources.long_range = false;
+        assert_eq!(
+            attack.effective_roll_mode(),
+            RollMode::Advantage { dice: 3 }
+        );
+        attack.roll_mode = RollMode::Normal;
+        attack.roll_sources = RollSources {
+            attacker_prone: true,
+            ..RollSources::default()
+        };
+        assert_eq!(attack.effective_roll_mode(), RollMode::Disadvantage);
+    }
+
+    #[test]
+    fn saved_session_loads_back_unchanged() {
+        let mut dnd = Dnd::default();
+        let mut build = Build {
+            name: "Rogue".to_string(),
+            lucky: true,
+            ..Build::default()
+        };
+        build.attacks[0].ab = 8;
+        dnd.builds.push(BuildSlot::new(build));
+        dnd.target.ac = 16;
+        dnd.target.resistance = true;
+        dnd.target.dr = 3;
+        dnd.target.miss_chance = 0.2;
+        dnd.target.defenses[DamageType::Fire as usize] = Defense::Immune;
+        dnd.desired_min_dmg = 25;
+        dnd.rounds = 3;
+        dnd.ac_range = (12, 22);
+        dnd.pinned_acs = vec![14, 20];
+        let session = dnd.session();
+
+        let json = serde_json::to_string(&session).unwrap();
+        let mut loaded = Dnd::default();
+        loaded.load_session(serde_json::from_str(&json).unwrap());
+        assert_eq!(loaded.session(), session);
+    }
+
+    #[test]
+    fn swapped_builds_keep_their_references() {
+        let mut dnd = Dnd::default();
+        dnd.builds[0].build.name = "Fighter".to_string();
+        dnd.builds[1].build.name = "Rogue".to_string();
+        dnd.copy_builds = (1, 0);
+        dnd.swap_builds(0, 1);
+
+        assert_eq!(dnd.builds[0].build.name, "Rogue");
+        assert_eq!(dnd.builds[1].build.name, "Fighter");
+        assert_eq!(dnd.encounter_rounds, vec![1, 0, 0]);
+        assert_eq!(dnd.difference_builds, (1, 0));
+        assert_eq!(dnd.copy_builds, (0, 1));
+        assert_eq!(dnd.last_edited, 1);
+    }
+}
@@ -1,42 +1,166 @@
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 
-use crate::AC_MAX;
-use crate::AC_MIN;
+use std::ops::RangeInclusive;
+
 use crate::Attack;
 use crate::Build;
+use crate::Chance;
+use crate::CritMultiplier;
+use crate::DAMAGE_TYPES;
+use crate::Defense;
 use crate::Die;
+use crate::HitRoll;
+use crate::RollMode;
+use crate::Save;
+use crate::Target;
 
-pub type PMF = HashMap<u32, f64>;
+pub type PMF = BTreeMap<u32, f64>;
 pub type CDF = Vec<(u32, f64)>;
+/// Distribution of the dmg difference between two builds, which can go negative.
+pub type DiffPMF = BTreeMap<i32, f64>;
 
 #[derive(Debug, Clone, Default)]
 pub struct Stats {
     pub pmf: PMF,
     pub cdf: CDF,
     pub mean: f64,
+    pub variance: f64,
     pub std_dev: f64,
-    pub greater_then_chance: f64,
+    // Std dev relative to the mean, lower means more consistent dmg. 0 when the mean is 0.
+    pub coefficient_of_variation: f64,
+    pub skewness: f64,
+    pub median: u32,
+    // Most likely dmg value, often 0 against high ACs because of all the misses.
+    pub mode: u32,
+    pub min_damage: u32,
+    pub max_damage: u32,
+    // Smallest nonzero dmg, the plain minimum is almost always 0 because of misses.
+    pub min_hit_damage: u32,
     pub min_dmg_chance: f64,
+    pub above_mean_chance: f64,
+    // Chance to land within one std dev of the mean, low for builds that rely on big crits.
+    pub within_std_dev_chance: f64,
+    // Standalone mean of every attack, in the same order as the build's attacks.
+    pub attack_means: Vec<f64>,
+    // Saves never hit or crit, so they don't count towards these.
+    pub expected_hits: f64,
+    pub expected_crits: f64,
+    // Chance that at least one attack hits, which is what once per turn riders care about.
+    pub any_hit_chance: f64,
+    // Chance that at least one attack crits.
+    pub any_crit_chance: f64,
+    // Mean dmg crits add on top of what the same attacks would deal as regular hits.
+    pub crit_uplift: f64,
+    // Distribution of the number of attacks that hit (crits included), saves don't count.
+    pub hit_counts: PMF,
+    pub contributions: Contributions,
+    pub chance_to_kill: f64,
+    // Expected dmg beyond the target's HP, given that it dies.
+    pub expected_overkill: f64,
+    // A single round without the nova attacks, only present when the build has any.
+    pub sustained: Option<Box<Stats>>,
 }
 
-fn hit_chance(ab: i32, ac: i32) -> f64 {
-    let needed_roll = (ac - ab).clamp(2, 20);
+/// Where the mean dmg comes from. Defenses, DR and Savage Attacker are left out, so the
+/// parts only roughly add up to the mean.
+#[derive(Debug, Clone, Default)]
+pub struct Contributions {
+    // Dice of every size, keyed by their number of sides. Crits only count them once.
+    pub dice: BTreeMap<u32, f64>,
+    pub flat: f64,
+    // Extra dice rolled on crits.
+    pub crit: f64,
+}
 
-    let possible_hits = 21 - needed_roll;
-    let mut chance = possible_hits as f64 / 20.0;
+impl Stats {
+    /// Smallest dmg value whose cumulative probability reaches `p`.
+    pub fn percentile(&self, p: f64) -> u32 {
+        self.percentiles([p])[0]
+    }
 
-    if ab + 1 >= ac {
-        chance -= 1.0 / 20.0;
+    /// Same as `percentile` for several values in a single pass, `ps` has to be ascending.
+    pub fn percentiles<const N: usize>(&self, ps: [f64; N]) -> [u32; N] {
+        // Float accumulation can leave the last entry just shy of 1.0.
+        let last = self.cdf.last().map(|&(dmg, _)| dmg).unwrap_or(0);
+        let mut result = [last; N];
+        let mut entries = self.cdf.iter().peekable();
+        for (i, p) in ps.into_iter().enumerate() {
+            while let Some(&&(dmg, cumulative)) = entries.peek() {
+                if cumulative >= p {
+                    result[i] = dmg;
+                    break;
+                }
+                entries.next();
+            }
+        }
+        result
     }
-    if ab + 20 < ac {
-        chance += 1.0 / 20.0;
+}
+
+/// Power attacks only make sense for weapon attacks, saves ignore the toggle.
+fn power_attack(attack: &Attack) -> bool {
+    attack.power_attack && attack.save.is_none()
+}
+
+/// Chance of a single d20 landing on `face`. Lucky rerolls a natural 1 once, so most of
+/// its chance gets spread evenly over all the faces.
+fn face_chance(face: i32, lucky: bool) -> f64 {
+    match (face, lucky) {
+        (_, false) => 1.0 / 20.0,
+        (1, true) => 1.0 / 400.0,
+        (_, true) => 1.0 / 20.0 + 1.0 / 400.0,
     }
+}
+
+/// Chance for a single d20 to hit. Every roll of `crit_from` or higher is a crit and crits
+/// always hit, so with an expanded crit range they never end up as misses. No matter how
+/// far the attack bonus exceeds the AC the chance tops out at 0.95 without Lucky.
+fn hit_chance(ab: i32, crit_from: i32, target: &Target, lucky: bool) -> f64 {
+    // Natural 1 always misses, every other face is compared against the AC using the
+    // target's rule.
+    (1..=20)
+        .filter(|&roll| match roll {
+            1 => false,
+            _ if roll >= crit_from => true,
+            _ => target.ac_comparison.hits(roll + ab, target.ac as i32),
+        })
+        .map(|roll| face_chance(roll, lucky))
+        .sum()
+}
+
+/// Smallest AB whose plain d20 roll hits the target at least `chance` of the time, `None`
+/// when even a natural 1 missing puts it out of reach. `hit_chance` only grows with the AB,
+/// so the first one that gets there is the answer. Below `ac - 21` only a natural 20 hits,
+/// so chances that low return that AB.
+pub fn min_ab_for_hit_chance(chance: f64, target: &Target) -> Option<i32> {
+    let ac = target.ac as i32;
+    // Float noise shouldn't make an exact 65% miss a 0.65 target.
+    ((ac - 21)..=(ac + 1)).find(|&ab| hit_chance(ab, 20, target, false) >= chance - 1e-9)
+}
+
+fn crit_chance(crit_from: i32, lucky: bool) -> f64 {
+    (crit_from..=20).map(|roll| face_chance(roll, lucky)).sum()
+}
 
-    chance.clamp(0.0, 1.0)
+/// Splits the target's saving throw into (failure, success, critical success) chances.
+/// Critical successes are only told apart when the save cares about them.
+fn save_chances(save: &Save, target: &Target) -> (f64, f64, f64) {
+    let mut chances = (0.0, 0.0, 0.0);
+    for roll in 1..=20 {
+        if roll == 20 && save.no_damage_on_crit_save {
+            chances.2 += 1.0 / 20.0;
+        } else if roll + target.save_bonus >= save.dc {
+            chances.1 += 1.0 / 20.0;
+        } else {
+            chances.0 += 1.0 / 20.0;
+        }
+    }
+    chances
 }
 
 fn convolve(a: &PMF, b: &PMF) -> PMF {
-    let mut result = HashMap::new();
+    let mut result = PMF::new();
     for (&x, &px) in a {
         for (&y, &py) in b {
             *result.entry(x + y).or_insert(0.0) += px * py;
@@ -45,82 +169,400 @@ fn convolve(a: &PMF, b: &PMF) -> PMF {
     result
 }
 
+/// Distribution of the sum. Summing nothing is a guaranteed 0, so an attack without any
+/// dice or flat dmg still has a valid distribution.
 fn convolve_many(pmfs: &[PMF]) -> PMF {
     pmfs.iter()
         .cloned()
         .reduce(|a, b| convolve(&a, &b))
-        .unwrap_or_default()
+        .unwrap_or_else(|| PMF::from([(0, 1.0)]))
 }
 
 fn scale(pmf: &PMF, factor: f64) -> PMF {
     pmf.iter().map(|(&k, &v)| (k, v * factor)).collect()
 }
 
-fn shift(pmf: &PMF, offset: u32) -> PMF {
-    pmf.iter().map(|(&k, &v)| (k + offset, v)).collect()
+/// Subtracts a flat amount from every outcome without going below 0.
+fn reduce(pmf: &PMF, amount: u32) -> PMF {
+    let mut result = PMF::new();
+    for (&k, &v) in pmf {
+        *result.entry(k.saturating_sub(amount)).or_default() += v;
+    }
+    result
+}
+
+fn halve(pmf: &PMF) -> PMF {
+    let mut result = PMF::new();
+    for (&k, &v) in pmf {
+        *result.entry(k / 2).or_default() += v;
+    }
+    result
+}
+
+fn double(pmf: &PMF) -> PMF {
+    pmf.iter().map(|(&k, &v)| (k * 2, v)).collect()
 }
 
-fn die_pmf(die: Die) -> PMF {
-    let mut pmf = HashMap::new();
-    let sides = die as u32;
+/// Faces below `min_face` count as `min_face`, like Elemental Adept turning 1s into 2s.
+fn die_pmf(sides: u32, min_face: u32) -> PMF {
+    let mut pmf = PMF::new();
     for i in 1..=sides {
-        pmf.insert(i, 1.0 / sides as f64);
+        *pmf.entry(i.max(min_face).min(sides)).or_default() += 1.0 / sides as f64;
     }
     pmf
 }
 
-fn attack_pmf(attack: &Attack, ac: u8, crit_enabled: bool, savage_attacker: bool) -> PMF {
-    let base_pmfs: Vec<_> = attack
-        .dice
+/// Exploding dice stop after this many extra rolls, the last one counts as rolled even on its
+/// max face. Without a cap the distribution would never end, and by then the chance left is
+/// negligible for any real die.
+pub const EXPLODE_LEVELS: u32 = 5;
+
+/// Rolling the max face of an exploding die adds another roll, which can explode again,
+/// up to `levels` extra rolls.
+fn explode(single: &PMF, sides: u32, levels: u32) -> PMF {
+    // Built from the last roll outwards, each pass allows one more explosion.
+    let mut pmf = single.clone();
+    for _ in 0..levels {
+        let mut next = PMF::new();
+        for (&face, &prob) in single {
+            if face == sides {
+                for (&rest, &rest_prob) in &pmf {
+                    *next.entry(face + rest).or_default() += prob * rest_prob;
+                }
+            } else {
+                *next.entry(face).or_default() += prob;
+            }
+        }
+        pmf = next;
+    }
+    pmf
+}
+
+/// Distribution of a single dmg die of the attack, with its min face and explosions.
+fn attack_die_pmf(attack: &Attack, sides: u32) -> PMF {
+    let single = die_pmf(sides, attack.min_face as u32);
+    if attack.explode {
+        explode(&single, sides, EXPLODE_LEVELS)
+    } else {
+        single
+    }
+}
+
+/// Flat dmg of a hit, `dice_multiplier` is 2 for a crit. Rolling the dmg twice only repeats the
+/// attack's own flat dmg, the power attack and crit bonuses are still added once.
+fn flat_dmg(attack: &Attack, dice_multiplier: u8) -> u32 {
+    let power = if power_attack(attack) { 10 } else { 0 };
+    let crit = if dice_multiplier >= 2 {
+        attack.crit_flat_bonus as u32
+    } else {
+        0
+    };
+    let times = if attack.roll_twice_sum && attack.roll_twice_flat {
+        2
+    } else {
+        1
+    };
+    attack.flat as u32 * times + power + crit
+}
+
+/// How many times `count` dice of an attack get rolled and the flat dmg they add on top,
+/// `dice_multiplier` is 2 for a crit.
+/// Rolling the dmg twice and summing is the same as convolving the dice with themselves, so
+/// it just rolls every die twice as often.
+fn dice_rolls(attack: &Attack, dice_multiplier: u8, sides: u32, count: u8) -> (u32, u32) {
+    // Counted in a wider type, a crit of 128 dice already rolls more than a `u8` can hold.
+    let count = count as u32;
+    let (rolls, bonus) = if dice_multiplier < 2 {
+        (count, 0)
+    } else {
+        match attack.crit_multiplier {
+            CritMultiplier::DoubleDice => (count * 2, 0),
+            CritMultiplier::TripleDice => (count * 3, 0),
+            CritMultiplier::MaxPlusRoll => (count, sides * count),
+        }
+    };
+    if attack.roll_twice_sum {
+        (rolls * 2, bonus * 2)
+    } else {
+        (rolls, bonus)
+    }
+}
+
+/// Rolls every damage type of the attack separately so the target's defenses can be
+/// applied to each of them before they get summed up.
+/// With `reroll_dice` every die is rolled twice keeping the better roll.
+fn typed_dmg_pmf(attack: &Attack, dice_multiplier: u8, target: &Target, reroll_dice: bool) -> PMF {
+    let flat = flat_dmg(attack, dice_multiplier);
+    let single_pmf = |sides: u32| {
+        let single = attack_die_pmf(attack, sides);
+        if reroll_dice {
+            best_of_two(&single)
+        } else {
+            single
+        }
+    };
+    let typed_pmfs: Vec<_> = DAMAGE_TYPES
         .iter()
-        .flat_map(|&(die, count)| {
-            let single = die_pmf(die);
-            std::iter::repeat(single).take(count as usize)
+        .filter_map(|&dmg_type| {
+            let dice = attack
+                .all_dice()
+                .filter(|&(_, count, die_type)| die_type == dmg_type && count > 0)
+                .map(|(sides, count, _)| {
+                    let (rolls, bonus) = dice_rolls(attack, dice_multiplier, sides, count);
+                    (sides, rolls as usize, bonus)
+                });
+            let mut pmfs: Vec<_> = match attack.reroll_lowest_pool() {
+                Some((sides, pool_type)) if pool_type == dmg_type => {
+                    let (rolls, bonus) =
+                        dice.fold((0, 0), |(rolls, bonus), (_, r, b)| (rolls + r, bonus + b));
+                    vec![
+                        reroll_lowest(&single_pmf(sides), rolls),
+                        PMF::from([(bonus, 1.0)]),
+                    ]
+                }
+                _ => dice
+                    .flat_map(|(sides, rolls, bonus)| {
+                        std::iter::repeat_n(single_pmf(sides), rolls)
+                            .chain((bonus > 0).then(|| PMF::from([(bonus, 1.0)])))
+                    })
+                    .collect(),
+            };
+            if attack.flat_type == dmg_type && flat > 0 {
+                pmfs.push(PMF::from([(flat, 1.0)]));
+            }
+            if pmfs.is_empty() {
+                return None;
+            }
+
+            let pmf = convolve_many(&pmfs);
+            Some(apply_defense(&pmf, target.defenses[dmg_type as usize]))
         })
         .collect();
 
-    let base_dmg_dist = convolve_many(&base_pmfs);
-    let base_pmf = shift(&base_dmg_dist, attack.flat as u32);
+    convolve_many(&typed_pmfs)
+}
 
-    let base_pmf = if savage_attacker {
-        best_of_two(&base_pmf)
-    } else {
-        base_pmf
-    };
+fn apply_defense(pmf: &PMF, defense: Defense) -> PMF {
+    match defense {
+        Defense::Normal => pmf.clone(),
+        Defense::Resistant => halve(pmf),
+        Defense::Immune => PMF::from([(0, 1.0)]),
+    }
+}
 
-    let crit_pmfs: Vec<_> = attack
-        .dice
+/// Once per turn dice along with the flat first hit bonus, which isn't doubled on a crit.
+/// They are dealt with the same type as the attack's flat dmg.
+fn sneak_pmf(build: &Build, dice_multiplier: u8) -> PMF {
+    let mut pmfs: Vec<_> = build
+        .sneak_dice
         .iter()
         .flat_map(|&(die, count)| {
-            let single = die_pmf(die);
-            std::iter::repeat(single).take((2 * count) as usize)
+            std::iter::repeat_n(
+                die_pmf(die as u32, 1),
+                dice_multiplier as usize * count as usize,
+            )
         })
         .collect();
+    pmfs.push(PMF::from([(build.first_hit_bonus, 1.0)]));
+    convolve_many(&pmfs)
+}
+
+fn has_once_per_turn(build: &Build) -> bool {
+    build.first_hit_bonus > 0 || build.sneak_dice.iter().any(|&(_, count)| count > 0)
+}
+
+/// Adds all of the outcomes of `b` into `a`.
+fn merge(a: &mut PMF, b: PMF) {
+    for (k, v) in b {
+        *a.entry(k).or_default() += v;
+    }
+}
+
+/// Dmg distributions of a regular hit and a crit. Neither of them depends on the AC,
+/// so they can be computed once per attack and reused for every AC.
+#[derive(Clone)]
+struct DicePmfs {
+    regular: (PMF, PMF),
+    // Same as `regular` but rolled twice keeping the better one. Only present for attacks
+    // Savage Attacker can be used with.
+    savage: Option<(PMF, PMF)>,
+}
+
+fn attack_dice_pmfs(attack: &Attack, target: &Target, build: &Build) -> DicePmfs {
+    let base_pmf = typed_dmg_pmf(attack, 1, target, false);
+    let crit_pmf = typed_dmg_pmf(attack, 2, target, false);
+
+    // Savage Attacker only works with weapon attacks, save based effects always roll once.
+    let savage = (build.savage && attack.save.is_none()).then(|| {
+        if build.savage_per_die {
+            (
+                best_of_two_per_die(attack, 1, target),
+                best_of_two_per_die(attack, 2, target),
+            )
+        } else {
+            (best_of_two(&base_pmf), best_of_two(&crit_pmf))
+        }
+    });
+
+    DicePmfs {
+        regular: (base_pmf, crit_pmf),
+        savage,
+    }
+}
+
+fn attack_pmf(
+    attack: &Attack,
+    (base_pmf, crit_pmf): &(PMF, PMF),
+    target: &Target,
+    build: &Build,
+) -> PMF {
+    // DR applies to every hit on its own, so it has to happen before attacks get summed up.
+    let base_pmf = reduce(base_pmf, target.dr);
+
+    if let Some(save) = &attack.save {
+        // Every target rolls its own save, so the total is the sum of independent copies.
+        return repeat_rounds(
+            &save_attack_pmf(&base_pmf, save, target),
+            save.targets.max(1),
+        );
+    }
+
+    let crit_pmf = reduce(crit_pmf, target.dr);
+
+    let (split_hit_chance, crit_chance) = attack_chances(attack, target, build);
+    let mut pmf = scale(&base_pmf, split_hit_chance);
+    merge(&mut pmf, scale(&crit_pmf, crit_chance));
+
+    // 0 dmg on miss.
+    *pmf.entry(0).or_insert(0.0) += 1.0 - split_hit_chance - crit_chance;
+    pmf
+}
+
+/// Chances of a regular (non crit) hit and of a crit.
+fn attack_chances(attack: &Attack, target: &Target, build: &Build) -> (f64, f64) {
+    let (split_hit_chance, crit_chance) = roll_chances(attack, target, build);
+    match target.hit_roll {
+        HitRoll::Rolled => {}
+        HitRoll::AlwaysHits => return (1.0 - crit_chance, crit_chance),
+        HitRoll::AlwaysHitsNoCrits => return (1.0, 0.0),
+    }
 
-    let crit_dmg_dist = convolve_many(&crit_pmfs);
-    let crit_pmf = shift(&crit_dmg_dist, attack.flat as u32);
+    // The miss chance is rolled after the attack hits, so it takes away from regular hits
+    // and crits alike.
+    let connects = 1.0 - target.miss_chance.clamp(0.0, 1.0);
+    let (split_hit_chance, crit_chance) = (split_hit_chance * connects, crit_chance * connects);
+
+    // A miss gets one more attempt with the very same chances, so it can crit as well.
+    if attack.reroll_on_miss {
+        let attempts = 2.0 - split_hit_chance - crit_chance;
+        (split_hit_chance * attempts, crit_chance * attempts)
+    } else {
+        (split_hit_chance, crit_chance)
+    }
+}
 
-    let crit_pmf = if savage_attacker {
-        best_of_two(&crit_pmf)
+/// Same as `attack_chances` but for a single attack roll, before the miss chance.
+fn roll_chances(attack: &Attack, target: &Target, build: &Build) -> (f64, f64) {
+    let ab = if power_attack(attack) {
+        attack.ab - 5
     } else {
-        crit_pmf
+        attack.ab
+    };
+    // Natural 20 always hits even without crits.
+    let crit_from = if build.crit_enabled {
+        attack.crit_range.clamp(2, 20) as i32
+    } else {
+        20
+    };
+    // Bless adds a d4 on top of whichever d20 is kept and Bane takes one away, so every d4
+    // face is equally likely no matter the roll mode. They do nothing for crits.
+    let hit_chance = if let Some(Chance(chance)) = attack.hit_override {
+        chance.clamp(0.0, 1.0)
+    } else if build.bless || build.bane {
+        let bless: &[i32] = if build.bless { &[1, 2, 3, 4] } else { &[0] };
+        let bane: &[i32] = if build.bane { &[1, 2, 3, 4] } else { &[0] };
+        bless
+            .iter()
+            .flat_map(|bless| bane.iter().map(move |bane| bless - bane))
+            .map(|bonus| {
+                attack.effective_roll_mode().chance(hit_chance(
+                    ab + bonus,
+                    crit_from,
+                    target,
+                    build.lucky,
+                ))
+            })
+            .sum::<f64>()
+            / (bless.len() * bane.len()) as f64
+    } else {
+        attack
+            .effective_roll_mode()
+            .chance(hit_chance(ab, crit_from, target, build.lucky))
+    };
+    let crit_chance = if build.crit_enabled {
+        attack
+            .effective_roll_mode()
+            .chance(crit_chance(crit_from, build.lucky))
+    } else {
+        0.0
     };
 
-    let hit_chance = hit_chance(attack.ab, ac as _);
-    let crit_chance = if crit_enabled { 1.0 / 20.0 } else { 0.0 };
+    // Crits are a subset of hits so this should never be negative, but float noise or a
+    // hit that is always a crit could push it below 0. In that case all of the hit mass
+    // belongs to the crit PMF.
+    let split_hit_chance = (hit_chance - crit_chance).max(0.0);
+    let crit_chance = hit_chance - split_hit_chance;
+    (split_hit_chance, crit_chance)
+}
 
-    let split_hit_chance = hit_chance - crit_chance;
-    let mut pmf = scale(&base_pmf, split_hit_chance);
-    let crit_pmf = scale(&crit_pmf, crit_chance);
+/// Saves can't crit, so the only thing that matters is how well the target rolls.
+fn save_attack_pmf(base_pmf: &PMF, save: &Save, target: &Target) -> PMF {
+    let (fail_chance, success_chance, crit_success_chance) = save_chances(save, target);
 
-    for (k, v) in crit_pmf {
-        *pmf.entry(k).or_default() += v;
+    let mut pmf = scale(base_pmf, fail_chance);
+    let mut no_dmg_chance = crit_success_chance;
+    if save.half_on_success {
+        let half_pmf = scale(&halve(base_pmf), success_chance);
+        for (k, v) in half_pmf {
+            *pmf.entry(k).or_default() += v;
+        }
+    } else {
+        no_dmg_chance += success_chance;
     }
 
-    *pmf.entry(0).or_insert(0.0) += 1.0 - hit_chance; // 0 dmg on miss.
+    *pmf.entry(0).or_insert(0.0) += no_dmg_chance;
     pmf
 }
 
+/// Sum of `count` dice distributed like `single` after the lowest of them is rolled once
+/// more, keeping the new roll even if it is worse.
+fn reroll_lowest(single: &PMF, count: usize) -> PMF {
+    if count == 0 {
+        return PMF::from([(0, 1.0)]);
+    }
+
+    // Joint distribution of the sum and the lowest die so far.
+    let mut pool: BTreeMap<(u32, u32), f64> = BTreeMap::from([((0, u32::MAX), 1.0)]);
+    for _ in 0..count {
+        let mut next = BTreeMap::new();
+        for (&(sum, lowest), &prob) in &pool {
+            for (&face, &face_prob) in single {
+                *next.entry((sum + face, lowest.min(face))).or_default() += prob * face_prob;
+            }
+        }
+        pool = next;
+    }
+
+    let mut result = PMF::new();
+    for ((sum, lowest), prob) in pool {
+        for (&face, &face_prob) in single {
+            *result.entry(sum - lowest + face).or_default() += prob * face_prob;
+        }
+    }
+    result
+}
+
+/// Rolls the whole thing twice and keeps the better total.
 fn best_of_two(pmf: &PMF) -> PMF {
     let mut result = PMF::new();
     for (&x, &px) in pmf {
@@ -132,34 +574,120 @@ fn best_of_two(pmf: &PMF) -> PMF {
     result
 }
 
+/// Unlike `best_of_two` on the dmg of the attack, every die keeps the better of its own two
+/// rolls. That favors the reroll more, as a low die can be swapped while keeping a high one.
+fn best_of_two_per_die(attack: &Attack, dice_multiplier: u8, target: &Target) -> PMF {
+    typed_dmg_pmf(attack, dice_multiplier, target, true)
+}
+
+fn mode(pmf: &PMF) -> u32 {
+    pmf.iter()
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(&val, _)| val)
+        .unwrap_or(0)
+}
+
+/// Smallest and largest dmg that can actually happen, plus the smallest nonzero one.
+fn damage_bounds(pmf: &PMF) -> (u32, u32, u32) {
+    let mut possible = pmf
+        .iter()
+        .filter(|(_, prob)| **prob > 0.0)
+        .map(|(&dmg, _)| dmg);
+    let min = possible.next().unwrap_or(0);
+    let max = possible.clone().next_back().unwrap_or(min);
+    let min_hit = if min > 0 {
+        min
+    } else {
+        possible.next().unwrap_or(0)
+    };
+    (min, max, min_hit)
+}
+
 fn mean(pmf: &PMF) -> f64 {
     pmf.iter().map(|(&val, &prob)| val as f64 * prob).sum()
 }
 
-fn variance(pmf: &PMF) -> f64 {
+fn central_moment(pmf: &PMF, order: i32) -> f64 {
     let mean = mean(pmf);
     pmf.iter()
-        .map(|(&val, &prob)| {
-            let diff = val as f64 - mean;
-            diff * diff * prob
-        })
+        .map(|(&val, &prob)| (val as f64 - mean).powi(order) * prob)
         .sum()
 }
 
+fn variance(pmf: &PMF) -> f64 {
+    central_moment(pmf, 2)
+}
+
+/// Positive when the right tail is longer, which crits tend to cause. 0 for a constant.
+fn skewness(pmf: &PMF) -> f64 {
+    let variance = variance(pmf);
+    if variance > 0.0 {
+        central_moment(pmf, 3) / variance.powf(1.5)
+    } else {
+        0.0
+    }
+}
+
 fn std_dev(pmf: &PMF) -> f64 {
     variance(pmf).sqrt()
 }
 
-pub fn greater_than(a: &PMF, b: &PMF) -> f64 {
-    let mut prob = 0.0;
+/// Chances that `a` deals more, the same or less dmg than `b`. Ties are common since both
+/// usually have a lot of mass at 0 from misses.
+pub fn outcome_probabilities(a: &PMF, b: &PMF) -> (f64, f64, f64) {
+    let mut outcomes = (0.0, 0.0, 0.0);
     for (&a_val, &a_prob) in a {
         for (&b_val, &b_prob) in b {
-            if a_val > b_val {
-                prob += a_prob * b_prob;
+            let prob = a_prob * b_prob;
+            match a_val.cmp(&b_val) {
+                std::cmp::Ordering::Greater => outcomes.0 += prob,
+                std::cmp::Ordering::Equal => outcomes.1 += prob,
+                std::cmp::Ordering::Less => outcomes.2 += prob,
+            }
+        }
+    }
+    outcomes
+}
+
+/// For every one of the independently rolled `pmfs`, the chance that it deals strictly more
+/// dmg than all of the others and the chance that it ties with some of them for the most.
+/// Like `outcome_probabilities` but against everyone at once.
+pub fn most_dmg_chances(pmfs: &[&PMF]) -> Vec<(f64, f64)> {
+    let cdfs: Vec<_> = pmfs.iter().map(|pmf| cdf(pmf)).collect();
+    // Chance of dealing less than `dmg`, or at most `dmg` when `inclusive` is set.
+    let below = |cdf: &CDF, dmg: u32, inclusive: bool| {
+        let idx = cdf.partition_point(|&(val, _)| val < dmg || (inclusive && val == dmg));
+        idx.checked_sub(1).map_or(0.0, |idx| cdf[idx].1)
+    };
+
+    pmfs.iter()
+        .enumerate()
+        .map(|(i, pmf)| {
+            let mut most = (0.0, 0.0);
+            for (&dmg, &prob) in pmf.iter() {
+                let others = cdfs.iter().enumerate().filter(|&(j, _)| j != i);
+                let all_below: f64 = others
+                    .clone()
+                    .map(|(_, cdf)| below(cdf, dmg, false))
+                    .product();
+                let none_above: f64 = others.map(|(_, cdf)| below(cdf, dmg, true)).product();
+                most.0 += prob * all_below;
+                most.1 += prob * (none_above - all_below);
             }
+            most
+        })
+        .collect()
+}
+
+/// Distribution of `a - b`, where `a` and `b` are rolled independently.
+pub fn difference_pmf(a: &PMF, b: &PMF) -> DiffPMF {
+    let mut result = DiffPMF::new();
+    for (&a_val, &a_prob) in a {
+        for (&b_val, &b_prob) in b {
+            *result.entry(a_val as i32 - b_val as i32).or_default() += a_prob * b_prob;
         }
     }
-    prob
+    result
 }
 
 fn chance_at_least(pmf: &PMF, threshold: u32) -> f64 {
@@ -169,14 +697,54 @@ fn chance_at_least(pmf: &PMF, threshold: u32) -> f64 {
         .sum()
 }
 
+fn expected_overkill(pmf: &PMF, hp: u32) -> f64 {
+    let kill_chance = chance_at_least(pmf, hp);
+    if kill_chance == 0.0 {
+        return 0.0;
+    }
+
+    let overkill: f64 = pmf
+        .range(hp..)
+        .map(|(&val, &prob)| (val - hp) as f64 * prob)
+        .sum();
+    overkill / kill_chance
+}
+
+fn chance_above(pmf: &PMF, threshold: f64) -> f64 {
+    pmf.iter()
+        .filter(|&(&val, _)| val as f64 > threshold)
+        .map(|(_, &prob)| prob)
+        .sum()
+}
+
+/// Chance of a dmg value in `[low, high]`, both ends included.
+fn chance_within(pmf: &PMF, low: f64, high: f64) -> f64 {
+    pmf.iter()
+        .filter(|&(&val, _)| (low..=high).contains(&(val as f64)))
+        .map(|(_, &prob)| prob)
+        .sum()
+}
+
+/// Chance to deal at least each dmg value from 0 up to the max, the same as
+/// `chance_at_least` for every threshold but in a single pass.
+pub fn survival(pmf: &PMF) -> Vec<(u32, f64)> {
+    let max = pmf.keys().last().copied().unwrap_or(0);
+    let mut remaining = 1.0;
+    (0..=max)
+        .map(|dmg| {
+            let at_least = remaining;
+            remaining -= pmf.get(&dmg).copied().unwrap_or(0.0);
+            (dmg, at_least.clamp(0.0, 1.0))
+        })
+        .collect()
+}
+
 fn cdf(pmf: &PMF) -> Vec<(u32, f64)> {
     let mut cumulative = 0.0;
     let mut result = Vec::new();
 
-    let mut values: Vec<_> = pmf.iter().collect();
-    values.sort_by_key(|&(&val, _)| val);
-
-    for (&val, &prob) in values {
+    // PMF keys are already sorted.
+    for (&val, &prob) in pmf {
         cumulative += prob;
         result.push((val, cumulative));
     }
@@ -184,36 +752,1993 @@ fn cdf(pmf: &PMF) -> Vec<(u32, f64)> {
     result
 }
 
-pub fn calc_build_stats(build: &Build, sim_ac: u8, desired_min_dmg: u32) -> Stats {
-    let mut stats = Stats::default();
-    stats.pmf = convolve_many(
-        &build
-            .attacks
+// Attack, target defenses, Savage Attacker and whether it rerolls per die.
+type DiceKey = (Attack, [Defense; DAMAGE_TYPES.len()], bool, bool);
+
+/// Dice distributions of attacks seen in earlier calculations, so editing a single attack
+/// only has to redo that one attack. Every worker keeps its own.
+#[derive(Default)]
+pub struct DiceCache {
+    entries: HashMap<DiceKey, DicePmfs>,
+}
+
+impl DiceCache {
+    // Plenty for a few builds worth of attacks while keeping memory in check.
+    const MAX_ENTRIES: usize = 64;
+
+    fn get(&mut self, attack: &Attack, target: &Target, build: &Build) -> DicePmfs {
+        let key = (
+            attack.clone(),
+            target.defenses,
+            build.savage,
+            build.savage_per_die,
+        );
+        if let Some(dice_pmfs) = self.entries.get(&key) {
+            return dice_pmfs.clone();
+        }
+
+        if self.entries.len() >= Self::MAX_ENTRIES {
+            self.entries.clear();
+        }
+        let dice_pmfs = attack_dice_pmfs(attack, target, build);
+        self.entries.insert(key, dice_pmfs.clone());
+        dice_pmfs
+    }
+}
+
+fn build_dice_pmfs(build: &Build, target: &Target, cache: &mut DiceCache) -> Vec<DicePmfs> {
+    build
+        .attacks
+        .iter()
+        .map(|a| cache.get(a, target, build))
+        .collect()
+}
+
+fn build_pmf(build: &Build, dice_pmfs: &[DicePmfs], target: &Target) -> PMF {
+    // An empty build never deals any dmg, but it still needs a valid distribution
+    // so that everything downstream (cdf, comparisons, plots) keeps working.
+    if build.attacks.is_empty() {
+        return PMF::from([(0, 1.0)]);
+    }
+
+    let mut attack_pmfs: Vec<_> = build
+        .attacks
+        .iter()
+        .zip(dice_pmfs)
+        .map(|(a, dice)| attack_pmf(a, &dice.regular, target, build))
+        .collect();
+
+    let savage = savage_attack(build, dice_pmfs, &attack_pmfs, target);
+    let has_sneak = has_once_per_turn(build);
+    let pmf = if has_sneak || build.attacks.iter().any(|a| a.on_crit_only) {
+        let savage_idx = savage.map(|(i, _)| i);
+        let dice: Vec<_> = dice_pmfs
             .iter()
-            .map(|a| attack_pmf(a, sim_ac, build.crit_enabled, build.savage))
-            .collect::<Vec<_>>(),
-    );
-    stats.cdf = cdf(&stats.pmf);
-    stats.mean = mean(&stats.pmf);
-    stats.std_dev = std_dev(&stats.pmf);
-    stats.min_dmg_chance = chance_at_least(&stats.pmf, desired_min_dmg);
+            .enumerate()
+            .map(|(i, dice)| match (&dice.savage, savage_idx == Some(i)) {
+                (Some(savage), true) => savage,
+                _ => &dice.regular,
+            })
+            .collect();
+        stateful_build_pmf(build, &dice, target)
+    } else {
+        if let Some((i, savage_pmf)) = savage {
+            attack_pmfs[i] = savage_pmf;
+        }
+        convolve_many(&attack_pmfs)
+    };
 
-    return stats;
+    // Same order as the rules, resistance first and vulnerability after it.
+    let pmf = if target.resistance { halve(&pmf) } else { pmf };
+    if target.vulnerable { double(&pmf) } else { pmf }
 }
 
-pub fn calc_build_means(build: &Build) -> Vec<f64> {
-    let means = (AC_MIN..AC_MAX)
-        .map(|ac| {
-            let pmf = convolve_many(
-                &build
-                    .attacks
-                    .iter()
-                    .map(|a| attack_pmf(a, ac, build.crit_enabled, build.savage))
-                    .collect::<Vec<_>>(),
-            );
-            mean(&pmf)
+/// Once per turn dice only land on the first attack that hits and bonus attacks only happen
+/// after an earlier attack crit, so with either of them the attacks can't be convolved
+/// independently. Instead this keeps a separate distribution for every combination of
+/// whether the dice were used and whether anything crit yet, and moves probability between
+/// them attack by attack.
+fn stateful_build_pmf(build: &Build, dice_pmfs: &[&(PMF, PMF)], target: &Target) -> PMF {
+    let has_sneak = has_once_per_turn(build);
+    let sneak = sneak_pmf(build, 1);
+    let sneak_crit = sneak_pmf(build, 2);
+
+    // Indexed by [dice used][crit seen]. Without any dice they count as used from the start.
+    let mut states: [[PMF; 2]; 2] = Default::default();
+    states[!has_sneak as usize][0] = PMF::from([(0, 1.0)]);
+    for (attack, dice) in build.attacks.iter().zip(dice_pmfs) {
+        // Every outcome of the attack as (dmg, hit, crit), once for when the dice are still
+        // unused and once for when they are.
+        let outcomes = |dice_used: bool| -> Vec<(PMF, bool, bool)> {
+            // Sneak Attack needs an attack roll, save based effects never trigger it.
+            if attack.save.is_some() {
+                return vec![(attack_pmf(attack, dice, target, build), false, false)];
+            }
+
+            let defense = target.defenses[attack.flat_type as usize];
+            let with_sneak = |dmg: &PMF, sneak: &PMF| {
+                reduce(&convolve(dmg, &apply_defense(sneak, defense)), target.dr)
+            };
+            let (base_pmf, crit_pmf) = dice;
+            let (hit, crit) = if dice_used {
+                (reduce(base_pmf, target.dr), reduce(crit_pmf, target.dr))
+            } else {
+                (
+                    with_sneak(base_pmf, &sneak),
+                    with_sneak(crit_pmf, &sneak_crit),
+                )
+            };
+            let (split_hit_chance, crit_chance) = attack_chances(attack, target, build);
+            vec![
+                (
+                    PMF::from([(0, 1.0 - split_hit_chance - crit_chance)]),
+                    false,
+                    false,
+                ),
+                (scale(&hit, split_hit_chance), true, false),
+                (scale(&crit, crit_chance), true, true),
+            ]
+        };
+        let outcomes = [outcomes(false), outcomes(true)];
+
+        let mut next: [[PMF; 2]; 2] = Default::default();
+        for dice_used in [false, true] {
+            for crit_seen in [false, true] {
+                let state = std::mem::take(&mut states[dice_used as usize][crit_seen as usize]);
+                if state.is_empty() {
+                    continue;
+                }
+                if attack.on_crit_only && !crit_seen {
+                    merge(&mut next[dice_used as usize][crit_seen as usize], state);
+                    continue;
+                }
+                for (pmf, hit, crit) in &outcomes[dice_used as usize] {
+                    let to = &mut next[(dice_used || *hit) as usize][(crit_seen || *crit) as usize];
+                    merge(to, convolve(&state, pmf));
+                }
+            }
+        }
+        states = next;
+    }
+
+    let mut pmf = PMF::new();
+    for state in states.into_iter().flatten() {
+        merge(&mut pmf, state);
+    }
+    pmf
+}
+
+/// Chance that each attack is made at all. Bonus attacks need an earlier attack to crit,
+/// every other attack always happens.
+fn attack_made_chances(build: &Build, target: &Target) -> Vec<f64> {
+    let mut no_crit_yet = 1.0;
+    build
+        .attacks
+        .iter()
+        .map(|a| {
+            let made = if a.on_crit_only {
+                1.0 - no_crit_yet
+            } else {
+                1.0
+            };
+            // Only attacks that are always made trigger bonus attacks, which is all that
+            // matters since a bonus attack can only follow a crit anyway.
+            if !a.on_crit_only && a.save.is_none() {
+                no_crit_yet *= 1.0 - attack_chances(a, target, build).1;
+            }
+            made
         })
-        .collect();
+        .collect()
+}
 
-    return means;
+/// Savage Attacker rerolls the dmg of a single attack per turn, so it goes to the attack
+/// that gains the most from it. Means add up, so this also maximizes the build's mean.
+/// Returns the index of that attack along with its rerolled distribution.
+fn savage_attack(
+    build: &Build,
+    dice_pmfs: &[DicePmfs],
+    attack_pmfs: &[PMF],
+    target: &Target,
+) -> Option<(usize, PMF)> {
+    build
+        .attacks
+        .iter()
+        .zip(dice_pmfs)
+        .enumerate()
+        .filter_map(|(i, (a, dice))| {
+            let savage_pmf = attack_pmf(a, dice.savage.as_ref()?, target, build);
+            let gain = mean(&savage_pmf) - mean(&attack_pmfs[i]);
+            Some((i, savage_pmf, gain))
+        })
+        .max_by(|(_, _, a), (_, _, b)| a.total_cmp(b))
+        .map(|(i, savage_pmf, _)| (i, savage_pmf))
+}
+
+/// SplitMix64, small and plenty good for rolling dice. Seeded so simulations are reproducible.
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    fn roll(&mut self, sides: u32) -> u32 {
+        (self.next_u64() % sides as u64) as u32 + 1
+    }
+
+    /// True with probability `p`.
+    fn chance(&mut self, p: f64) -> bool {
+        ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < p
+    }
+
+    fn roll_d20(&mut self, roll_mode: RollMode, lucky: bool) -> i32 {
+        let mut single = || match self.roll(20) {
+            1 if lucky => self.roll(20),
+            roll => roll,
+        };
+        let roll = match roll_mode {
+            RollMode::Normal => single(),
+            RollMode::Advantage { dice } => (0..dice).map(|_| single()).max().unwrap_or(1),
+            RollMode::Disadvantage => single().min(single()),
+        };
+        roll as i32
+    }
+}
+
+/// Rolls the dmg of a single hit, with every damage type going through the target's defenses.
+/// With `reroll_dice` every die is rolled twice keeping the better roll.
+fn roll_dmg(
+    rng: &mut Rng,
+    attack: &Attack,
+    dice_multiplier: u8,
+    target: &Target,
+    reroll_dice: bool,
+) -> u32 {
+    let flat = flat_dmg(attack, dice_multiplier);
+    DAMAGE_TYPES
+        .iter()
+        .map(|&dmg_type| {
+            let mut dmg = 0;
+            let roll_face = |rng: &mut Rng, sides: u32| {
+                let explosions = if attack.explode { EXPLODE_LEVELS } else { 0 };
+                let mut face = || {
+                    let mut total = 0;
+                    for _ in 0..=explosions {
+                        let face = rng.roll(sides).max(attack.min_face as u32).min(sides);
+                        total += face;
+                        if face < sides {
+                            break;
+                        }
+                    }
+                    total
+                };
+                if reroll_dice {
+                    face().max(face())
+                } else {
+                    face()
+                }
+            };
+            let mut lowest = None;
+            for (sides, count, _) in attack
+                .all_dice()
+                .filter(|&(_, _, die_type)| die_type == dmg_type)
+            {
+                let (rolls, bonus) = dice_rolls(attack, dice_multiplier, sides, count);
+                dmg += bonus;
+                for _ in 0..rolls {
+                    let face = roll_face(rng, sides);
+                    dmg += face;
+                    lowest = Some(lowest.map_or(face, |lowest: u32| lowest.min(face)));
+                }
+            }
+            if let (Some((sides, pool_type)), Some(lowest)) = (attack.reroll_lowest_pool(), lowest)
+                && pool_type == dmg_type
+            {
+                dmg = dmg - lowest + roll_face(rng, sides);
+            }
+            if attack.flat_type == dmg_type {
+                dmg += flat;
+            }
+            match target.defenses[dmg_type as usize] {
+                Defense::Normal => dmg,
+                Defense::Resistant => dmg / 2,
+                Defense::Immune => 0,
+            }
+        })
+        .sum()
+}
+
+/// Returns the dmg dealt and the dice multiplier of the hit, 2 for a crit and 0 for a miss.
+/// `sneak_dice` are added on a hit, together with the build's first hit bonus.
+fn simulate_attack(
+    rng: &mut Rng,
+    attack: &Attack,
+    target: &Target,
+    build: &Build,
+    savage_attacker: bool,
+    sneak_dice: Option<&[(Die, u8)]>,
+) -> (u32, u8) {
+    let hit_dmg = |rng: &mut Rng, dice_multiplier: u8| {
+        let dmg =
+            match (savage_attacker, build.savage_per_die) {
+                (true, true) => roll_dmg(rng, attack, dice_multiplier, target, true),
+                (true, false) => roll_dmg(rng, attack, dice_multiplier, target, false)
+                    .max(roll_dmg(rng, attack, dice_multiplier, target, false)),
+                (false, _) => roll_dmg(rng, attack, dice_multiplier, target, false),
+            };
+        let sneak: u32 = sneak_dice
+            .unwrap_or_default()
+            .iter()
+            .flat_map(|&(die, count)| {
+                std::iter::repeat_n(die, dice_multiplier as usize * count as usize)
+            })
+            .map(|die| rng.roll(die as u32))
+            .sum::<u32>()
+            + sneak_dice.map_or(0, |_| build.first_hit_bonus);
+        let sneak = match target.defenses[attack.flat_type as usize] {
+            Defense::Normal => sneak,
+            Defense::Resistant => sneak / 2,
+            Defense::Immune => 0,
+        };
+        (dmg + sneak).saturating_sub(target.dr)
+    };
+
+    if let Some(save) = &attack.save {
+        let dmg = (0..save.targets.max(1))
+            .map(|_| {
+                let dmg = roll_dmg(rng, attack, 1, target, false).saturating_sub(target.dr);
+                let roll = rng.roll(20) as i32;
+                if roll == 20 && save.no_damage_on_crit_save {
+                    0
+                } else if roll + target.save_bonus >= save.dc {
+                    if save.half_on_success { dmg / 2 } else { 0 }
+                } else {
+                    dmg
+                }
+            })
+            .sum();
+        return (dmg, 0);
+    }
+
+    let ab = if power_attack(attack) {
+        attack.ab - 5
+    } else {
+        attack.ab
+    };
+    let crit_from = if build.crit_enabled {
+        attack.crit_range.clamp(2, 20) as i32
+    } else {
+        20
+    };
+    match target.hit_roll {
+        HitRoll::Rolled => {}
+        HitRoll::AlwaysHits => {
+            let roll = rng.roll_d20(attack.effective_roll_mode(), build.lucky);
+            let dice_multiplier = if roll >= crit_from && build.crit_enabled {
+                2
+            } else {
+                1
+            };
+            return (hit_dmg(rng, dice_multiplier), dice_multiplier);
+        }
+        HitRoll::AlwaysHitsNoCrits => return (hit_dmg(rng, 1), 1),
+    }
+
+    let attempts = if attack.reroll_on_miss { 2 } else { 1 };
+    for _ in 0..attempts {
+        let bless = if build.bless { rng.roll(4) as i32 } else { 0 }
+            - if build.bane { rng.roll(4) as i32 } else { 0 };
+        // A fixed hit chance has no d20 to look at, so draw straight from the chances.
+        let dice_multiplier = if attack.hit_override.is_some() {
+            let (split_hit_chance, crit_chance) = roll_chances(attack, target, build);
+            if rng.chance(crit_chance) {
+                2
+            } else if rng.chance(split_hit_chance / (1.0 - crit_chance)) {
+                1
+            } else {
+                continue;
+            }
+        } else {
+            match rng.roll_d20(attack.effective_roll_mode(), build.lucky) {
+                1 => continue,
+                roll if roll >= crit_from && build.crit_enabled => 2,
+                roll if roll >= crit_from
+                    || target
+                        .ac_comparison
+                        .hits(roll + ab + bless, target.ac as i32) =>
+                {
+                    1
+                }
+                _ => continue,
+            }
+        };
+        if rng.chance(target.miss_chance) {
+            continue;
+        }
+        return (hit_dmg(rng, dice_multiplier), dice_multiplier);
+    }
+    (0, 0)
+}
+
+/// Empirical distribution from actually rolling the build `n_samples` times. Meant as a
+/// cross-check for the exact distribution from `calc_build_stats`.
+pub fn simulate_build(build: &Build, target: &Target, n_samples: u32, seed: u64) -> PMF {
+    // Savage Attacker goes to the same attack the exact calculation picks.
+    let dice_pmfs = build_dice_pmfs(build, target, &mut DiceCache::default());
+    let attack_pmfs: Vec<_> = build
+        .attacks
+        .iter()
+        .zip(&dice_pmfs)
+        .map(|(a, dice)| attack_pmf(a, &dice.regular, target, build))
+        .collect();
+    let savage_idx = savage_attack(build, &dice_pmfs, &attack_pmfs, target).map(|(i, _)| i);
+
+    let mut rng = Rng(seed);
+    let mut counts = BTreeMap::<u32, u32>::new();
+    for _ in 0..n_samples {
+        let mut dmg = 0;
+        let mut sneak_dice = Some(&build.sneak_dice[..]);
+        let mut crit_seen = false;
+        for (i, attack) in build.attacks.iter().enumerate() {
+            if attack.on_crit_only && !crit_seen {
+                continue;
+            }
+            let (attack_dmg, dice_multiplier) = simulate_attack(
+                &mut rng,
+                attack,
+                target,
+                build,
+                savage_idx == Some(i),
+                sneak_dice,
+            );
+            dmg += attack_dmg;
+            if dice_multiplier > 0 {
+                sneak_dice = None;
+            }
+            crit_seen |= dice_multiplier == 2;
+        }
+        let dmg = if target.resistance { dmg / 2 } else { dmg };
+        let dmg = if target.vulnerable { dmg * 2 } else { dmg };
+        *counts.entry(dmg).or_default() += 1;
+    }
+
+    counts
+        .into_iter()
+        .map(|(dmg, count)| (dmg, count as f64 / n_samples as f64))
+        .collect()
+}
+
+/// How far a simulated distribution is off from the exact one, as the absolute difference
+/// of the means and the largest difference in the chance of any single dmg value.
+pub fn pmf_discrepancy(exact: &PMF, simulated: &PMF) -> (f64, f64) {
+    let mean_diff = (mean(exact) - mean(simulated)).abs();
+    let max_diff = exact
+        .keys()
+        .chain(simulated.keys())
+        .map(|dmg| {
+            let exact = exact.get(dmg).copied().unwrap_or(0.0);
+            let simulated = simulated.get(dmg).copied().unwrap_or(0.0);
+            (exact - simulated).abs()
+        })
+        .fold(0.0, f64::max);
+    (mean_diff, max_diff)
+}
+
+/// Total dmg of `rounds` independent rounds. Uses exponentiation by squaring so only
+/// a logarithmic number of convolutions is needed.
+pub fn repeat_rounds(pmf: &PMF, rounds: u32) -> PMF {
+    let mut result = PMF::from([(0, 1.0)]);
+    let mut power = pmf.clone();
+    let mut rounds = rounds;
+    while rounds > 0 {
+        if rounds & 1 == 1 {
+            result = convolve(&result, &power);
+        }
+        rounds >>= 1;
+        if rounds > 0 {
+            power = convolve(&power, &power);
+        }
+    }
+    result
+}
+
+/// ACs at which two mean dmg curves over the same ACs cross, interpolated between the
+/// neighbouring ACs.
+pub fn crossover_acs(a: &[f64], b: &[f64], ac_min: u8) -> Vec<f64> {
+    let diffs: Vec<f64> = a.iter().zip(b).map(|(a, b)| a - b).collect();
+    diffs
+        .windows(2)
+        .enumerate()
+        .filter(|(_, pair)| pair[0] * pair[1] < 0.0)
+        .map(|(offset, pair)| ac_min as f64 + offset as f64 + pair[0] / (pair[0] - pair[1]))
+        .collect()
+}
+
+/// AC at which `means` is furthest above the best of `others` at that AC, along with that
+/// margin. All of them have to cover the same ACs, starting at `ac_min`.
+pub fn best_margin_ac(means: &[f64], others: &[&[f64]], ac_min: u8) -> Option<(u8, f64)> {
+    if others.is_empty() {
+        return None;
+    }
+    means
+        .iter()
+        .enumerate()
+        .map(|(offset, mean)| {
+            let best_other = others
+                .iter()
+                .filter_map(|other| other.get(offset))
+                .fold(f64::NEG_INFINITY, |a, &b| a.max(b));
+            (ac_min + offset as u8, mean - best_other)
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+}
+
+/// AC from which the mean drops the most going one AC up, along with that drop. This is
+/// where the build's hit chances fall off the hardest.
+pub fn steepest_drop_ac(means: &[f64], ac_min: u8) -> Option<(u8, f64)> {
+    means
+        .windows(2)
+        .enumerate()
+        .map(|(offset, pair)| (ac_min + offset as u8, pair[0] - pair[1]))
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+}
+
+/// Mean of every attack on its own. These add up to the build mean unless once per turn dice
+/// or resistance or vulnerability to all dmg are involved.
+fn attack_means(build: &Build, dice_pmfs: &[DicePmfs], target: &Target) -> Vec<f64> {
+    let mut attack_pmfs: Vec<_> = build
+        .attacks
+        .iter()
+        .zip(dice_pmfs)
+        .map(|(a, dice)| attack_pmf(a, &dice.regular, target, build))
+        .collect();
+    if let Some((i, savage_pmf)) = savage_attack(build, dice_pmfs, &attack_pmfs, target) {
+        attack_pmfs[i] = savage_pmf;
+    }
+
+    attack_pmfs
+        .iter()
+        .zip(attack_made_chances(build, target))
+        .map(|(pmf, made)| mean(pmf) * made)
+        .collect()
+}
+
+pub fn calc_build_stats(
+    build: &Build,
+    target: &Target,
+    desired_min_dmg: u32,
+    cache: &mut DiceCache,
+) -> Stats {
+    let dice_pmfs = build_dice_pmfs(build, target, cache);
+    let pmf = build_pmf(build, &dice_pmfs, target);
+    let mut stats = pmf_stats(pmf, target, desired_min_dmg);
+    stats.attack_means = attack_means(build, &dice_pmfs, target);
+    (stats.expected_hits, stats.expected_crits) = expected_hits(build, target);
+    stats.any_hit_chance = any_hit_chance(build, target);
+    stats.any_crit_chance = any_crit_chance(build, target);
+    stats.crit_uplift = crit_uplift(build, &dice_pmfs, target);
+    stats.hit_counts = hit_count_pmf(build, target);
+    stats.contributions = contributions(build, target);
+    stats.sustained = sustained_build(build)
+        .map(|sustained| Box::new(calc_build_stats(&sustained, target, desired_min_dmg, cache)));
+    stats
+}
+
+/// First AC in `acs` at which each power attack deals at least as much mean dmg without it, in
+/// the same order as the attacks. `None` for attacks without one, or when it pays off at every
+/// AC in `acs`.
+pub fn power_attack_break_even(
+    build: &Build,
+    target: &Target,
+    acs: RangeInclusive<u8>,
+) -> Vec<Option<u8>> {
+    build
+        .attacks
+        .iter()
+        .map(|attack| {
+            if !power_attack(attack) {
+                return None;
+            }
+            let normal = Attack {
+                power_attack: false,
+                ..attack.clone()
+            };
+            let power_dice = attack_dice_pmfs(attack, target, build);
+            let normal_dice = attack_dice_pmfs(&normal, target, build);
+            acs.clone().find(|&ac| {
+                let target = Target { ac, ..*target };
+                mean(&attack_pmf(&normal, &normal_dice.regular, &target, build))
+                    >= mean(&attack_pmf(attack, &power_dice.regular, &target, build))
+            })
+        })
+        .collect()
+}
+
+/// The build as it is in every round after the first, with all of the once per rest attacks
+/// used up. `None` when there is nothing to use up.
+pub fn sustained_build(build: &Build) -> Option<Build> {
+    if !build.attacks.iter().any(|a| a.nova) {
+        return None;
+    }
+    let mut sustained = build.clone();
+    sustained.attacks.retain(|a| !a.nova);
+    Some(sustained)
+}
+
+/// Total dmg of `rounds` rounds where the first one deals `first` and every other `later`.
+pub fn nova_rounds(first: &PMF, later: &PMF, rounds: u32) -> PMF {
+    if rounds == 0 {
+        return PMF::from([(0, 1.0)]);
+    }
+    convolve(first, &repeat_rounds(later, rounds - 1))
+}
+
+/// Every attack either hits or it doesn't, so this is a convolution of those coin flips.
+/// Bonus attacks depend on an earlier crit, so they are tracked separately for whether a
+/// crit already happened, the same way `stateful_build_pmf` does.
+pub fn hit_count_pmf(build: &Build, target: &Target) -> PMF {
+    // Indexed by whether anything crit yet.
+    let mut states: [PMF; 2] = [PMF::from([(0, 1.0)]), PMF::new()];
+    for attack in build.attacks.iter().filter(|a| a.save.is_none()) {
+        let (split_hit_chance, crit_chance) = attack_chances(attack, target, build);
+        let outcomes = [
+            (1.0 - split_hit_chance - crit_chance, 0, false),
+            (split_hit_chance, 1, false),
+            (crit_chance, 1, true),
+        ];
+
+        let mut next: [PMF; 2] = Default::default();
+        for crit_seen in [false, true] {
+            let state = std::mem::take(&mut states[crit_seen as usize]);
+            if attack.on_crit_only && !crit_seen {
+                merge(&mut next[0], state);
+                continue;
+            }
+            for (hits, chance) in state {
+                for &(outcome_chance, hit, crit) in &outcomes {
+                    *next[(crit_seen || crit) as usize]
+                        .entry(hits + hit)
+                        .or_default() += chance * outcome_chance;
+                }
+            }
+        }
+        states = next;
+    }
+
+    let [mut pmf, crit_seen] = states;
+    merge(&mut pmf, crit_seen);
+    pmf
+}
+
+fn contributions(build: &Build, target: &Target) -> Contributions {
+    let mut contributions = Contributions::default();
+    for (attack, made) in build.attacks.iter().zip(attack_made_chances(build, target)) {
+        // Chance that the attack deals its dmg at all, and the part of it that is a crit.
+        let (dmg_chance, crit_chance) = if let Some(save) = &attack.save {
+            let (fail_chance, success_chance, _) = save_chances(save, target);
+            let half = if save.half_on_success { 0.5 } else { 0.0 };
+            let targets = save.targets.max(1) as f64;
+            ((fail_chance + success_chance * half) * targets, 0.0)
+        } else {
+            let (split_hit_chance, crit_chance) = attack_chances(attack, target, build);
+            (split_hit_chance + crit_chance, crit_chance)
+        };
+
+        for (sides, count, _) in attack.all_dice() {
+            let single_mean = mean(&attack_die_pmf(attack, sides));
+            let (regular_rolls, _) = dice_rolls(attack, 1, sides, count);
+            let dice_mean = single_mean * regular_rolls as f64;
+            let (rolls, bonus) = dice_rolls(attack, 2, sides, count);
+            let crit_extra = single_mean * rolls as f64 + bonus as f64 - dice_mean;
+            *contributions.dice.entry(sides).or_default() += dice_mean * dmg_chance * made;
+            contributions.crit += crit_extra * crit_chance * made;
+        }
+        contributions.flat += flat_dmg(attack, 1) as f64 * dmg_chance * made;
+        contributions.crit += attack.crit_flat_bonus as f64 * crit_chance * made;
+    }
+
+    // Once per turn dice land on the first hit, crits among them aren't tracked here.
+    let any_hit_chance = any_hit_chance(build, target);
+    for &(die, count) in &build.sneak_dice {
+        let dice_mean = mean(&die_pmf(die as u32, 1)) * count as f64;
+        *contributions.dice.entry(die as u32).or_default() += dice_mean * any_hit_chance;
+    }
+    contributions.flat += build.first_hit_bonus as f64 * any_hit_chance;
+
+    contributions.dice.retain(|_, mean| *mean > 0.0);
+    contributions
+}
+
+fn any_hit_chance(build: &Build, target: &Target) -> f64 {
+    // Bonus attacks only follow a crit, which already was a hit.
+    let all_miss: f64 = build
+        .attacks
+        .iter()
+        .filter(|a| a.save.is_none() && !a.on_crit_only)
+        .map(|a| {
+            let (split_hit, crit) = attack_chances(a, target, build);
+            1.0 - split_hit - crit
+        })
+        .product();
+    1.0 - all_miss
+}
+
+/// Bonus attacks only follow a crit, so they can't change whether there was one.
+fn any_crit_chance(build: &Build, target: &Target) -> f64 {
+    let no_crit: f64 = build
+        .attacks
+        .iter()
+        .filter(|a| a.save.is_none() && !a.on_crit_only)
+        .map(|a| 1.0 - attack_chances(a, target, build).1)
+        .product();
+    1.0 - no_crit
+}
+
+/// Difference between the mean of every attack and its mean when crits deal regular hit dmg.
+/// Unlike the crit contribution this includes defenses and DR, but once per turn dice,
+/// Savage Attacker and resistance or vulnerability to all dmg are still left out.
+fn crit_uplift(build: &Build, dice_pmfs: &[DicePmfs], target: &Target) -> f64 {
+    build
+        .attacks
+        .iter()
+        .zip(dice_pmfs)
+        .zip(attack_made_chances(build, target))
+        .map(|((attack, dice), made)| {
+            let (base_pmf, _) = &dice.regular;
+            let no_crit_dice = (base_pmf.clone(), base_pmf.clone());
+            let with_crits = mean(&attack_pmf(attack, &dice.regular, target, build));
+            let without_crits = mean(&attack_pmf(attack, &no_crit_dice, target, build));
+            (with_crits - without_crits) * made
+        })
+        .sum()
+}
+
+/// Expected number of hits (crits included) and crits over all attacks.
+fn expected_hits(build: &Build, target: &Target) -> (f64, f64) {
+    build
+        .attacks
+        .iter()
+        .zip(attack_made_chances(build, target))
+        .filter(|(a, _)| a.save.is_none())
+        .map(|(a, made)| {
+            let (split_hit, crit) = attack_chances(a, target, build);
+            (split_hit * made, crit * made)
+        })
+        .fold((0.0, 0.0), |(hits, crits), (split_hit, crit)| {
+            (hits + split_hit + crit, crits + crit)
+        })
+}
+
+/// Same as `calc_build_stats` but for the total dmg over several rounds. Nova attacks only
+/// happen in the first round, every other round is the sustained one.
+pub fn calc_rounds_stats(
+    round: &Stats,
+    build: &Build,
+    rounds: u32,
+    target: &Target,
+    desired_min_dmg: u32,
+) -> Stats {
+    let later = round.sustained.as_deref().unwrap_or(round);
+    let later_rounds = rounds.saturating_sub(1);
+    let total = |first: f64, later: f64| first + later * later_rounds as f64;
+
+    let pmf = nova_rounds(&round.pmf, &later.pmf, rounds);
+    let mut stats = pmf_stats(pmf, target, desired_min_dmg);
+    // The sustained build has the nova attacks taken out, so its means skip them.
+    let mut later_means = later.attack_means.iter();
+    stats.attack_means = round
+        .attack_means
+        .iter()
+        .zip(&build.attacks)
+        .map(|(&mean, attack)| {
+            if attack.nova {
+                mean
+            } else {
+                total(mean, later_means.next().copied().unwrap_or(0.0))
+            }
+        })
+        .collect();
+    stats.expected_hits = total(round.expected_hits, later.expected_hits);
+    stats.expected_crits = total(round.expected_crits, later.expected_crits);
+    stats.any_hit_chance =
+        1.0 - (1.0 - round.any_hit_chance) * (1.0 - later.any_hit_chance).powi(later_rounds as i32);
+    stats.any_crit_chance = 1.0
+        - (1.0 - round.any_crit_chance) * (1.0 - later.any_crit_chance).powi(later_rounds as i32);
+    stats.crit_uplift = total(round.crit_uplift, later.crit_uplift);
+    stats.hit_counts = nova_rounds(&round.hit_counts, &later.hit_counts, rounds);
+    let mut dice = round.contributions.dice.clone();
+    for (&sides, &mean) in &later.contributions.dice {
+        *dice.entry(sides).or_default() += mean * later_rounds as f64;
+    }
+    stats.contributions = Contributions {
+        dice,
+        flat: total(round.contributions.flat, later.contributions.flat),
+        crit: total(round.contributions.crit, later.contributions.crit),
+    };
+    stats.sustained = round.sustained.clone();
+    stats
+}
+
+fn pmf_stats(pmf: PMF, target: &Target, desired_min_dmg: u32) -> Stats {
+    let mut stats = Stats::default();
+    stats.pmf = pmf;
+    stats.cdf = cdf(&stats.pmf);
+    stats.mean = mean(&stats.pmf);
+    stats.variance = variance(&stats.pmf);
+    stats.std_dev = stats.variance.sqrt();
+    stats.coefficient_of_variation = if stats.mean > 0.0 {
+        stats.std_dev / stats.mean
+    } else {
+        0.0
+    };
+    stats.skewness = skewness(&stats.pmf);
+    stats.median = stats.percentile(0.5);
+    stats.mode = mode(&stats.pmf);
+    (stats.min_damage, stats.max_damage, stats.min_hit_damage) = damage_bounds(&stats.pmf);
+    stats.min_dmg_chance = chance_at_least(&stats.pmf, desired_min_dmg);
+    stats.above_mean_chance = chance_above(&stats.pmf, stats.mean);
+    stats.within_std_dev_chance = chance_within(
+        &stats.pmf,
+        stats.mean - stats.std_dev,
+        stats.mean + stats.std_dev,
+    );
+    stats.chance_to_kill = chance_at_least(&stats.pmf, target.hp);
+    stats.expected_overkill = expected_overkill(&stats.pmf, target.hp);
+
+    return stats;
+}
+
+/// Full stats for a handful of specific ACs, same as calling `calc_build_stats` for each.
+pub fn calc_pinned_stats(
+    build: &Build,
+    target: &Target,
+    desired_min_dmg: u32,
+    pinned_acs: &[u8],
+    cache: &mut DiceCache,
+) -> Vec<Stats> {
+    pinned_acs
+        .iter()
+        .map(|&ac| calc_build_stats(build, &Target { ac, ..*target }, desired_min_dmg, cache))
+        .collect()
+}
+
+/// Switches one of the build-wide features on or off.
+type FeatureToggle = fn(&mut Build) -> &mut bool;
+
+/// Tries a handful of small upgrades to the build: one more die on an attack, one more attack,
+/// +1 to hit and Savage Attacker, Bless or Lucky when the build doesn't use them yet. Crits are a
+/// table rule rather than an upgrade, so they're left alone. Returns the upgrade that adds the
+/// most mean dmg per round against the target together with that gain, `None` when nothing
+/// helps.
+pub fn suggest_improvement(
+    build: &Build,
+    target: &Target,
+    cache: &mut DiceCache,
+) -> Option<(String, f64)> {
+    let mut candidates = Vec::new();
+    for (i, attack) in build.attacks.iter().enumerate() {
+        // More of the biggest die the attack already rolls, a d6 when it has none.
+        let die = attack
+            .dice
+            .iter()
+            .filter(|&&(_, count, _)| count > 0)
+            .map(|&(die, _, _)| die)
+            .max_by_key(|&die| die as u32)
+            .unwrap_or(Die::D6);
+        let mut upgraded = build.clone();
+        if let Some((_, count, _)) = upgraded.attacks[i]
+            .dice
+            .iter_mut()
+            .find(|(d, _, _)| *d == die)
+        {
+            *count = count.saturating_add(1);
+        }
+        candidates.push((
+            format!("+1 D{} on {}", die as u32, attack.label(i)),
+            upgraded,
+        ));
+    }
+    if !build.attacks.is_empty() {
+        let mut upgraded = build.clone();
+        upgraded.add_attack();
+        candidates.push(("One more attack".to_string(), upgraded));
+
+        let mut upgraded = build.clone();
+        for attack in &mut upgraded.attacks {
+            attack.ab += 1;
+        }
+        candidates.push(("+1 to hit on every attack".to_string(), upgraded));
+    }
+    const FEATURES: [(&str, FeatureToggle); 3] = [
+        ("Savage Attacker", |b| &mut b.savage),
+        ("Bless", |b| &mut b.bless),
+        ("Lucky", |b| &mut b.lucky),
+    ];
+    for (label, feature) in FEATURES {
+        let mut upgraded = build.clone();
+        let enabled = feature(&mut upgraded);
+        if !*enabled {
+            *enabled = true;
+            candidates.push((format!("Enable {label}"), upgraded));
+        }
+    }
+
+    // Only the mean matters here, so skip the rest of `calc_build_stats`.
+    let mut mean_dmg = |build: &Build| {
+        let dice_pmfs = build_dice_pmfs(build, target, cache);
+        mean(&build_pmf(build, &dice_pmfs, target))
+    };
+    let base = mean_dmg(build);
+    candidates
+        .into_iter()
+        .map(|(description, upgraded)| (description, mean_dmg(&upgraded) - base))
+        .filter(|&(_, gain)| gain > 1e-9)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+/// Stats for a whole encounter, where each round deals dmg according to its own distribution.
+pub fn calc_encounter_stats(rounds: &[&PMF]) -> Stats {
+    let mut stats = Stats::default();
+    stats.pmf = rounds
+        .iter()
+        .fold(PMF::from([(0, 1.0)]), |acc, pmf| convolve(&acc, pmf));
+    stats.cdf = cdf(&stats.pmf);
+    stats.mean = mean(&stats.pmf);
+    stats.std_dev = std_dev(&stats.pmf);
+
+    stats
+}
+
+/// Mean dmg for every AC in `acs`.
+pub fn calc_build_means(
+    build: &Build,
+    target: &Target,
+    acs: RangeInclusive<u8>,
+    cache: &mut DiceCache,
+) -> Vec<(f64, f64)> {
+    let dice_pmfs = build_dice_pmfs(build, target, cache);
+    let means = acs
+        .map(|ac| {
+            let pmf = build_pmf(build, &dice_pmfs, &Target { ac, ..*target });
+            (mean(&pmf), std_dev(&pmf))
+        })
+        .collect();
+
+    return means;
+}
+
+/// Means of the build with every power attack turned off, `None` when it doesn't use any.
+pub fn calc_no_power_attack_means(
+    build: &Build,
+    target: &Target,
+    acs: RangeInclusive<u8>,
+    cache: &mut DiceCache,
+) -> Option<Vec<f64>> {
+    if !build.attacks.iter().any(power_attack) {
+        return None;
+    }
+
+    let mut no_power = build.clone();
+    for attack in &mut no_power.attacks {
+        attack.power_attack = false;
+    }
+    let means = calc_build_means(&no_power, target, acs, cache);
+    Some(means.into_iter().map(|(mean, _)| mean).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AcComparison;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    /// Checks that all of the chance is on `dmg`, other entries can be left at 0.
+    fn assert_fixed(pmf: &PMF, dmg: u32) {
+        assert_close(pmf.get(&dmg).copied().unwrap_or(0.0), 1.0);
+        assert_close(pmf.values().sum(), 1.0);
+    }
+
+    fn target(ac: u8) -> Target {
+        Target {
+            ac,
+            ..Target::default()
+        }
+    }
+
+    /// Attack that only deals its flat dmg, with none of the default dice.
+    fn flat_attack(ab: i32, flat: u8) -> Attack {
+        let mut attack = Attack {
+            ab,
+            flat,
+            ..Attack::default()
+        };
+        for (_, count, _) in &mut attack.dice {
+            *count = 0;
+        }
+        attack
+    }
+
+    #[test]
+    fn hit_chance_tops_out_at_a_natural_one_missing() {
+        // Hitting on a 2 and hitting on anything both leave only the natural 1.
+        assert_close(hit_chance(8, 20, &target(10), false), 0.95);
+        assert_close(hit_chance(9, 20, &target(10), false), 0.95);
+        assert_close(hit_chance(30, 20, &target(10), false), 0.95);
+        assert_close(hit_chance(10, 20, &target(10), false), 0.95);
+    }
+
+    #[test]
+    fn hit_chance_counts_the_faces_that_meet_the_ac() {
+        assert_close(hit_chance(5, 20, &target(15), false), 0.55);
+        assert_close(hit_chance(0, 20, &target(10), false), 0.55);
+        assert_close(hit_chance(7, 20, &target(10), false), 0.9);
+    }
+
+    #[test]
+    fn hit_chance_falls_back_to_a_natural_twenty() {
+        assert_close(hit_chance(0, 20, &target(30), false), 0.05);
+        assert_close(hit_chance(-10, 20, &target(10), false), 0.05);
+        // A 20 that would hit anyway doesn't count twice.
+        assert_close(hit_chance(0, 20, &target(20), false), 0.05);
+    }
+
+    #[test]
+    fn hit_chance_always_hits_in_the_crit_range() {
+        assert_close(hit_chance(0, 19, &target(30), false), 0.1);
+        assert_close(hit_chance(0, 18, &target(30), false), 0.15);
+        // Once the AC is low enough the crit range doesn't add any hits.
+        assert_close(hit_chance(5, 19, &target(15), false), 0.55);
+    }
+
+    #[test]
+    fn crits_take_all_of_the_hits_when_nothing_else_hits() {
+        for roll_mode in [
+            RollMode::Normal,
+            RollMode::Advantage { dice: 2 },
+            RollMode::Advantage { dice: 3 },
+            RollMode::Disadvantage,
+        ] {
+            for crit_range in [20, 19, 18, 15] {
+                let attack = Attack {
+                    ab: 0,
+                    crit_range,
+                    roll_mode,
+                    ..Attack::default()
+                };
+                let build = Build {
+                    attacks: vec![attack.clone()],
+                    ..Build::default()
+                };
+                let high_ac = target(30);
+
+                let (split_hit_chance, crit_chance) = roll_chances(&attack, &high_ac, &build);
+                assert!(split_hit_chance >= 0.0 && crit_chance >= 0.0);
+                let hit = roll_mode.chance(hit_chance(0, crit_range as i32, &high_ac, false));
+                assert_close(split_hit_chance + crit_chance, hit);
+                assert_close(split_hit_chance, 0.0);
+
+                let stats = calc_build_stats(&build, &high_ac, 0, &mut DiceCache::default());
+                assert!(stats.pmf.values().all(|&prob| prob >= 0.0));
+                assert_close(stats.pmf.values().sum(), 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn crit_multipliers_scale_the_dice_only() {
+        let mut attack = Attack {
+            flat: 3,
+            ..Attack::default()
+        };
+        for (die, count, _) in &mut attack.dice {
+            *count = if *die == Die::D6 { 2 } else { 0 };
+        }
+        for (crit_multiplier, crit_mean) in [
+            (CritMultiplier::DoubleDice, 4.0 * 3.5 + 3.0),
+            (CritMultiplier::TripleDice, 6.0 * 3.5 + 3.0),
+            (CritMultiplier::MaxPlusRoll, 12.0 + 2.0 * 3.5 + 3.0),
+        ] {
+            let attack = Attack {
+                crit_multiplier,
+                ..attack.clone()
+            };
+            assert_close(mean(&typed_dmg_pmf(&attack, 1, &target(10), false)), 10.0);
+            assert_close(
+                mean(&typed_dmg_pmf(&attack, 2, &target(10), false)),
+                crit_mean,
+            );
+        }
+    }
+
+    #[test]
+    fn crit_flat_bonus_only_adds_to_crits() {
+        let mut attack = Attack {
+            flat: 3,
+            ..Attack::default()
+        };
+        let hit = mean(&typed_dmg_pmf(&attack, 1, &target(10), false));
+        let crit = mean(&typed_dmg_pmf(&attack, 2, &target(10), false));
+        attack.crit_flat_bonus = 5;
+        assert_close(mean(&typed_dmg_pmf(&attack, 1, &target(10), false)), hit);
+        assert_close(
+            mean(&typed_dmg_pmf(&attack, 2, &target(10), false)),
+            crit + 5.0,
+        );
+    }
+
+    #[test]
+    fn saves_mix_full_and_floored_half_dmg() {
+        let mut attack = Attack {
+            flat: 9,
+            save: Some(Save {
+                dc: 15,
+                half_on_success: true,
+                no_damage_on_crit_save: false,
+                targets: 1,
+            }),
+            ..Attack::default()
+        };
+        for (_, count, _) in &mut attack.dice {
+            *count = 0;
+        }
+        let build = Build {
+            attacks: vec![attack.clone()],
+            ..Build::default()
+        };
+        // Saves succeed on a 12 or higher with the +3 bonus.
+        let target = Target {
+            save_bonus: 3,
+            ..target(10)
+        };
+
+        let dice = attack_dice_pmfs(&attack, &target, &build);
+        let pmf = attack_pmf(&attack, &dice.regular, &target, &build);
+        assert_eq!(pmf.values().filter(|&&prob| prob > 0.0).count(), 2);
+        assert_close(pmf[&9], 0.55);
+        assert_close(pmf[&4], 0.45);
+        assert_close(mean(&pmf), 9.0 * 0.55 + 4.0 * 0.45);
+    }
+
+    #[test]
+    fn a_natural_twenty_save_can_take_no_dmg() {
+        let attack = Attack {
+            save: Some(Save {
+                dc: 15,
+                half_on_success: true,
+                no_damage_on_crit_save: true,
+                targets: 1,
+            }),
+            ..flat_attack(0, 9)
+        };
+        let build = Build {
+            attacks: vec![attack.clone()],
+            ..Build::default()
+        };
+        let target = Target {
+            save_bonus: 3,
+            ..target(10)
+        };
+
+        // The natural 20 slice of the successes deals nothing instead of half.
+        let dice = attack_dice_pmfs(&attack, &target, &build);
+        let pmf = attack_pmf(&attack, &dice.regular, &target, &build);
+        assert_close(pmf[&9], 0.55);
+        assert_close(pmf[&4], 0.4);
+        assert_close(pmf[&0], 0.05);
+    }
+
+    #[test]
+    fn area_saves_hit_every_target() {
+        let attack = Attack {
+            save: Some(Save {
+                dc: 30,
+                targets: 3,
+                ..Save::default()
+            }),
+            ..flat_attack(0, 10)
+        };
+        let build = Build {
+            attacks: vec![attack.clone()],
+            ..Build::default()
+        };
+        // None of the targets can make a DC 30 save.
+        let dice = attack_dice_pmfs(&attack, &target(10), &build);
+        let pmf = attack_pmf(&attack, &dice.regular, &target(10), &build);
+        assert_fixed(&pmf, 30);
+    }
+
+    #[test]
+    fn reroll_lowest_keeps_the_new_roll() {
+        let d6 = die_pmf(6, 1);
+        // A single die is always the lowest, and a reroll of it is just another d6.
+        assert_close(mean(&reroll_lowest(&d6, 1)), 3.5);
+        // With two dice the higher one stays, and the lower one becomes a fresh d6.
+        let max_of_two: f64 = (1..=6)
+            .map(|face| (2 * face - 1) as f64 * face as f64)
+            .sum();
+        assert_close(mean(&reroll_lowest(&d6, 2)), max_of_two / 36.0 + 3.5);
+        assert_close(reroll_lowest(&d6, 3).values().sum(), 1.0);
+    }
+
+    #[test]
+    fn crit_dice_counts_dont_overflow() {
+        let attack = Attack {
+            crit_multiplier: CritMultiplier::TripleDice,
+            ..Attack::default()
+        };
+        assert_eq!(dice_rolls(&attack, 2, 6, 200), (600, 0));
+        let attack = Attack {
+            crit_multiplier: CritMultiplier::DoubleDice,
+            ..attack
+        };
+        assert_eq!(dice_rolls(&attack, 2, 6, 200), (400, 0));
+        let attack = Attack {
+            roll_twice_sum: true,
+            ..attack
+        };
+        assert_eq!(dice_rolls(&attack, 2, 6, u8::MAX), (1020, 0));
+        assert_eq!(dice_rolls(&attack, 1, 6, 128), (256, 0));
+    }
+
+    #[test]
+    fn power_attack_breaks_even_at_the_first_ac_it_stops_paying_off() {
+        let attack = Attack {
+            ab: 5,
+            flat: 4,
+            power_attack: true,
+            ..Attack::default()
+        };
+        let build = Build {
+            attacks: vec![attack.clone(), Attack::default()],
+            ..Build::default()
+        };
+        let attack_mean = |attack: &Attack, ac: u8| {
+            let dice = attack_dice_pmfs(attack, &target(ac), &build);
+            mean(&attack_pmf(attack, &dice.regular, &target(ac), &build))
+        };
+        let normal = Attack {
+            power_attack: false,
+            ..attack.clone()
+        };
+
+        let break_even = power_attack_break_even(&build, &target(10), 0..=30);
+        assert_eq!(break_even[1], None);
+        let ac = break_even[0].expect("-5/+10 on a +4 attack stops paying off somewhere");
+        assert!(attack_mean(&normal, ac) >= attack_mean(&attack, ac));
+        assert!(attack_mean(&normal, ac - 1) < attack_mean(&attack, ac - 1));
+
+        // Only the ACs in the range are looked at.
+        assert_eq!(
+            power_attack_break_even(&build, &target(10), ac..=30)[0],
+            Some(ac)
+        );
+        assert_eq!(
+            power_attack_break_even(&build, &target(10), 0..=ac - 1)[0],
+            None
+        );
+    }
+
+    #[test]
+    fn must_exceed_misses_when_the_roll_only_meets_the_ac() {
+        assert!(AcComparison::MeetsOrBeats.hits(15, 15));
+        assert!(!AcComparison::MustExceed.hits(15, 15));
+        assert!(AcComparison::MustExceed.hits(16, 15));
+
+        // With +5 against AC 15 a 10 meets the AC exactly, which only counts under the
+        // default rule.
+        let must_exceed = Target {
+            ac_comparison: AcComparison::MustExceed,
+            ..target(15)
+        };
+        assert_close(hit_chance(5, 20, &target(15), false), 0.55);
+        assert_close(hit_chance(5, 20, &must_exceed, false), 0.5);
+    }
+
+    #[test]
+    fn cached_dice_give_the_same_means_as_recomputing_them() {
+        let build = Build {
+            attacks: vec![
+                Attack {
+                    ab: 7,
+                    flat: 4,
+                    ..Attack::default()
+                },
+                Attack {
+                    ab: 3,
+                    power_attack: true,
+                    crit_range: 19,
+                    ..Attack::default()
+                },
+            ],
+            ..Build::default()
+        };
+        let mut cache = DiceCache::default();
+        let means = calc_build_means(&build, &target(10), 5..=25, &mut cache);
+        let fresh: Vec<_> = (5..=25)
+            .map(|ac| {
+                let stats = calc_build_stats(&build, &target(ac), 0, &mut DiceCache::default());
+                (stats.mean, stats.std_dev)
+            })
+            .collect();
+        assert_eq!(means, fresh);
+        // A second pass only hits the cache.
+        assert_eq!(
+            calc_build_means(&build, &target(10), 5..=25, &mut cache),
+            means
+        );
+    }
+
+    #[test]
+    fn flat_dmg_without_dice_is_dealt_in_full() {
+        assert_eq!(convolve_many(&[]), PMF::from([(0, 1.0)]));
+
+        let attack = flat_attack(5, 5);
+        let build = Build {
+            attacks: vec![attack.clone()],
+            ..Build::default()
+        };
+        let dice = attack_dice_pmfs(&attack, &target(15), &build);
+        assert_eq!(dice.regular.0, PMF::from([(5, 1.0)]));
+
+        let stats = calc_build_stats(&build, &target(15), 0, &mut DiceCache::default());
+        let dealt: Vec<_> = stats.pmf.iter().filter(|&(_, &prob)| prob > 0.0).collect();
+        assert_eq!(dealt.len(), 2);
+        assert_close(stats.pmf[&0], 0.45);
+        assert_close(stats.pmf[&5], 0.55);
+    }
+
+    #[test]
+    fn bonus_attack_only_follows_a_crit() {
+        let build = Build {
+            attacks: vec![
+                flat_attack(5, 1),
+                Attack {
+                    on_crit_only: true,
+                    ..flat_attack(30, 10)
+                },
+            ],
+            ..Build::default()
+        };
+        let ac = target(15);
+        let made = attack_made_chances(&build, &ac);
+        assert_close(made[0], 1.0);
+        assert_close(made[1], 0.05);
+
+        // The first attack misses 45% of the time, otherwise it deals 1. Its 5% of crits
+        // are followed by the bonus attack, which hits on anything but a natural 1.
+        let stats = calc_build_stats(&build, &ac, 0, &mut DiceCache::default());
+        assert_close(stats.pmf[&0], 0.45);
+        assert_close(stats.pmf[&1], 0.5 + 0.05 * 0.05);
+        assert_close(stats.pmf[&11], 0.05 * 0.95);
+        assert_close(stats.mean, 0.5025 + 11.0 * 0.0475);
+    }
+
+    #[test]
+    fn identical_builds_split_the_most_dmg_evenly() {
+        let coin = PMF::from([(0, 0.5), (1, 0.5)]);
+        for (win, tie) in most_dmg_chances(&[&coin, &coin, &coin]) {
+            // Only a lone 1 wins outright. A 1 shared with someone else is a tie, and so are
+            // three 0s.
+            assert_close(win, 0.125);
+            assert_close(tie, 0.5 * 0.75 + 0.125);
+        }
+
+        let build = Build::default();
+        let pmf = calc_build_stats(&build, &target(15), 0, &mut DiceCache::default()).pmf;
+        let chances = most_dmg_chances(&[&pmf, &pmf, &pmf]);
+        let (win, tie) = chances[0];
+        // Ties take their share, so none of them gets to a third on their own.
+        assert!(win < 1.0 / 3.0);
+        assert!(tie > 0.0);
+        for &(other_win, other_tie) in &chances[1..] {
+            assert_close(other_win, win);
+            assert_close(other_tie, tie);
+        }
+    }
+
+    #[test]
+    fn min_ab_for_hit_chance_inverts_hit_chance() {
+        let ac = target(18);
+        // Hitting on an 8 covers 13 faces.
+        assert_eq!(min_ab_for_hit_chance(0.65, &ac), Some(10));
+        assert_eq!(min_ab_for_hit_chance(0.62, &ac), Some(10));
+        assert_eq!(min_ab_for_hit_chance(0.66, &ac), Some(11));
+
+        // A natural 1 always misses and a natural 20 always hits.
+        assert_eq!(min_ab_for_hit_chance(0.95, &ac), Some(16));
+        assert_eq!(min_ab_for_hit_chance(0.96, &ac), None);
+        assert_eq!(min_ab_for_hit_chance(0.05, &ac), Some(-3));
+        assert_eq!(min_ab_for_hit_chance(0.0, &ac), Some(-3));
+
+        // Every AB between the two boundaries gets a chance of its own.
+        for ab in -1..=16 {
+            let chance = hit_chance(ab, 20, &ac, false);
+            assert_eq!(min_ab_for_hit_chance(chance, &ac), Some(ab));
+        }
+    }
+
+    #[test]
+    fn exploding_d6_converges_to_its_uncapped_mean() {
+        let d6 = die_pmf(6, 1);
+        assert_close(mean(&explode(&d6, 6, 0)), 3.5);
+
+        // Every explosion adds another 3.5 a sixth as often, which adds up to 3.5 * 6/5.
+        let mut prev = 3.5;
+        for levels in 1..=8 {
+            let pmf = explode(&d6, 6, levels);
+            assert_close(pmf.values().sum(), 1.0);
+            let expected: f64 = (0..=levels).map(|i| 3.5 / 6f64.powi(i as i32)).sum();
+            assert_close(mean(&pmf), expected);
+            assert!(mean(&pmf) > prev && mean(&pmf) < 4.2);
+            prev = mean(&pmf);
+        }
+        assert!(4.2 - prev < 1e-5);
+
+        let attack = Attack {
+            explode: true,
+            ..Attack::default()
+        };
+        assert_close(
+            mean(&attack_die_pmf(&attack, 6)),
+            mean(&explode(&d6, 6, EXPLODE_LEVELS)),
+        );
+    }
+
+    #[test]
+    fn roll_modes_keep_the_right_die() {
+        // Hitting on an 11 is a coin flip for a single d20.
+        let single = hit_chance(0, 20, &target(11), false);
+        assert_close(single, 0.5);
+        assert_close(RollMode::Normal.chance(single), 0.5);
+        assert_close(RollMode::Advantage { dice: 2 }.chance(single), 0.75);
+        assert_close(RollMode::Advantage { dice: 3 }.chance(single), 0.875);
+        assert_close(RollMode::Disadvantage.chance(single), 0.25);
+
+        for roll_mode in [
+            RollMode::Normal,
+            RollMode::Advantage { dice: 2 },
+            RollMode::Advantage { dice: 3 },
+            RollMode::Disadvantage,
+        ] {
+            let build = Build {
+                attacks: vec![Attack {
+                    roll_mode,
+                    ..Attack::default()
+                }],
+                ..Build::default()
+            };
+            let stats = calc_build_stats(&build, &target(18), 0, &mut DiceCache::default());
+            assert_close(stats.pmf.values().sum(), 1.0);
+        }
+
+        // A third die is one more chance at a 20.
+        let crit = |roll_mode| {
+            let attack = Attack {
+                roll_mode,
+                ..Attack::default()
+            };
+            roll_chances(&attack, &target(18), &Build::default()).1
+        };
+        let (two, three) = (
+            crit(RollMode::Advantage { dice: 2 }),
+            crit(RollMode::Advantage { dice: 3 }),
+        );
+        assert_close(two, 1.0 - 0.95f64.powi(2));
+        assert_close(three, 1.0 - 0.95f64.powi(3));
+        assert!(three > two);
+    }
+
+    #[test]
+    fn lucky_only_moves_chance_off_the_natural_one() {
+        assert_close((1..=20).map(|face| face_chance(face, true)).sum(), 1.0);
+
+        // Only a natural 1 on the reroll still misses.
+        assert_close(hit_chance(30, 20, &target(10), true), 1.0 - 1.0 / 400.0);
+        // Hitting on an 11 gets a tenth of the rerolled 1s.
+        assert_close(hit_chance(0, 20, &target(11), true), 0.5 + 10.0 / 400.0);
+
+        let build = Build {
+            attacks: vec![Attack {
+                roll_mode: RollMode::Advantage { dice: 2 },
+                ..Attack::default()
+            }],
+            lucky: true,
+            ..Build::default()
+        };
+        let stats = calc_build_stats(&build, &target(18), 0, &mut DiceCache::default());
+        assert_close(stats.pmf.values().sum(), 1.0);
+    }
+
+    #[test]
+    fn rolling_dmg_twice_sums_both_rolls() {
+        let mut attack = flat_attack(5, 0);
+        attack.dice[1].1 = 1;
+        attack.roll_twice_sum = true;
+        // A single d6 rolled twice and added up.
+        assert_close(mean(&typed_dmg_pmf(&attack, 1, &target(15), false)), 7.0);
+
+        attack.flat = 3;
+        assert_close(mean(&typed_dmg_pmf(&attack, 1, &target(15), false)), 10.0);
+        attack.roll_twice_flat = true;
+        assert_close(mean(&typed_dmg_pmf(&attack, 1, &target(15), false)), 13.0);
+
+        // The power attack and crit bonuses come on top of both rolls only once.
+        attack.power_attack = true;
+        attack.crit_flat_bonus = 2;
+        assert_close(mean(&typed_dmg_pmf(&attack, 1, &target(15), false)), 23.0);
+        assert_close(mean(&typed_dmg_pmf(&attack, 2, &target(15), false)), 32.0);
+    }
+
+    #[test]
+    fn bless_adds_an_average_d4_to_hit() {
+        let attack = flat_attack(5, 1);
+        let mut build = Build {
+            attacks: vec![attack.clone()],
+            ..Build::default()
+        };
+        let (hit, crit) = roll_chances(&attack, &target(15), &build);
+        assert_close(hit + crit, 0.55);
+        build.bless = true;
+        let (blessed_hit, blessed_crit) = roll_chances(&attack, &target(15), &build);
+        // +1 to +4 hit on a 9 down to a 6.
+        assert_close(blessed_hit + blessed_crit, 0.675);
+        assert_close(blessed_crit, crit);
+    }
+
+    #[test]
+    fn bane_takes_an_average_d4_off_to_hit() {
+        let attack = flat_attack(5, 1);
+        let build = Build {
+            attacks: vec![attack.clone()],
+            bane: true,
+            ..Build::default()
+        };
+        let (hit, crit) = roll_chances(&attack, &target(15), &build);
+        // -1 to -4 hit on an 11 up to a 14.
+        assert_close(hit + crit, 0.425);
+        assert_close(crit, 0.05);
+    }
+
+    #[test]
+    fn bane_cant_take_away_a_natural_twenty() {
+        let attack = flat_attack(0, 1);
+        let build = Build {
+            attacks: vec![attack.clone()],
+            bane: true,
+            ..Build::default()
+        };
+        // Hitting on a 19 without Bane, any penalty leaves only the natural 20.
+        let (hit, crit) = roll_chances(&attack, &target(19), &build);
+        assert_close(hit, 0.0);
+        assert_close(crit, 0.05);
+        let (hit, crit) = roll_chances(&attack, &target(30), &build);
+        assert_close(hit, 0.0);
+        assert_close(crit, 0.05);
+    }
+
+    #[test]
+    fn hit_override_replaces_the_roll() {
+        let attack = Attack {
+            hit_override: Some(Chance(0.5)),
+            ..flat_attack(0, 6)
+        };
+        let mut build = Build {
+            attacks: vec![attack.clone()],
+            crit_enabled: false,
+            ..Build::default()
+        };
+        // The AC doesn't matter anymore.
+        for ac in [5, 30] {
+            assert_eq!(roll_chances(&attack, &target(ac), &build), (0.5, 0.0));
+            let stats = calc_build_stats(&build, &target(ac), 0, &mut DiceCache::default());
+            assert_close(stats.pmf[&0], 0.5);
+            assert_close(stats.pmf[&6], 0.5);
+        }
+
+        // Crits still come out of the overridden chance.
+        build.crit_enabled = true;
+        let (hit, crit) = roll_chances(&attack, &target(15), &build);
+        assert_close(hit, 0.45);
+        assert_close(crit, 0.05);
+        let attack = Attack {
+            crit_range: 19,
+            ..attack
+        };
+        let (hit, crit) = roll_chances(&attack, &target(15), &build);
+        assert_close(hit, 0.4);
+        assert_close(crit, 0.1);
+    }
+
+    #[test]
+    fn resistance_halves_the_total() {
+        let build = Build {
+            attacks: vec![flat_attack(0, 10)],
+            ..Build::default()
+        };
+        let target = Target {
+            hit_roll: HitRoll::AlwaysHitsNoCrits,
+            resistance: true,
+            ..target(10)
+        };
+        let stats = calc_build_stats(&build, &target, 0, &mut DiceCache::default());
+        assert_fixed(&stats.pmf, 5);
+    }
+
+    #[test]
+    fn vulnerability_doubles_the_mean() {
+        let build = Build {
+            attacks: vec![Attack::default(), Attack::default()],
+            ..Build::default()
+        };
+        let vulnerable = Target {
+            vulnerable: true,
+            ..target(15)
+        };
+        let normal = calc_build_stats(&build, &target(15), 0, &mut DiceCache::default());
+        let doubled = calc_build_stats(&build, &vulnerable, 0, &mut DiceCache::default());
+        assert_close(doubled.mean, 2.0 * normal.mean);
+        assert_close(doubled.std_dev, 2.0 * normal.std_dev);
+    }
+
+    #[test]
+    fn dr_comes_off_every_hit() {
+        let build = Build {
+            attacks: vec![flat_attack(5, 8)],
+            ..Build::default()
+        };
+        let target = Target {
+            dr: 3,
+            ..target(15)
+        };
+        let stats = calc_build_stats(&build, &target, 0, &mut DiceCache::default());
+        assert_close(stats.pmf[&0], 0.45);
+        assert_close(stats.pmf[&5], 0.55);
+        assert_close(stats.pmf.values().sum(), 1.0);
+
+        // It never heals.
+        let target = Target { dr: 20, ..target };
+        let stats = calc_build_stats(&build, &target, 0, &mut DiceCache::default());
+        assert_fixed(&stats.pmf, 0);
+    }
+
+    #[test]
+    fn miss_chance_takes_from_hits_and_crits_alike() {
+        let attack = flat_attack(5, 4);
+        let build = Build {
+            attacks: vec![attack.clone()],
+            ..Build::default()
+        };
+        let target = Target {
+            miss_chance: 0.2,
+            ..target(15)
+        };
+        let (hit, crit) = attack_chances(&attack, &target, &build);
+        assert_close(hit, 0.5 * 0.8);
+        assert_close(crit, 0.05 * 0.8);
+
+        let stats = calc_build_stats(&build, &target, 0, &mut DiceCache::default());
+        assert_close(stats.pmf[&0], 0.45 + 0.55 * 0.2);
+        assert_close(stats.pmf[&4], 0.55 * 0.8);
+    }
+
+    #[test]
+    fn percentiles_take_the_first_dmg_that_reaches_them() {
+        let stats = pmf_stats(PMF::from([(0, 0.25), (4, 0.25), (10, 0.5)]), &target(10), 0);
+        assert_eq!(stats.percentile(0.0), 0);
+        assert_eq!(stats.percentile(0.25), 0);
+        assert_eq!(stats.percentile(0.3), 4);
+        assert_eq!(stats.percentile(0.5), 4);
+        assert_eq!(stats.percentile(1.0), 10);
+        assert_eq!(stats.percentiles([0.1, 0.5, 0.9]), [0, 4, 10]);
+
+        // Nothing to go by, so everything is 0.
+        assert_eq!(Stats::default().percentile(0.5), 0);
+        assert_eq!(Stats::default().percentiles([0.0, 1.0]), [0, 0]);
+    }
+
+    #[test]
+    fn median_and_mode_by_hand() {
+        // Misses are the most likely outcome, but not half of them.
+        let stats = pmf_stats(PMF::from([(0, 0.4), (3, 0.35), (8, 0.25)]), &target(10), 0);
+        assert_eq!(stats.median, 3);
+        assert_eq!(stats.mode, 0);
+
+        let stats = pmf_stats(PMF::from([(1, 0.2), (2, 0.3), (5, 0.5)]), &target(10), 0);
+        assert_eq!(stats.median, 2);
+        assert_eq!(stats.mode, 5);
+    }
+
+    #[test]
+    fn damage_bounds_of_a_fixed_build() {
+        let mut attack = flat_attack(5, 2);
+        attack.dice[1].1 = 1;
+        let build = Build {
+            attacks: vec![attack],
+            ..Build::default()
+        };
+        // Misses deal 0, a hit 1d6+2 and a crit 2d6+2.
+        let stats = calc_build_stats(&build, &target(15), 0, &mut DiceCache::default());
+        assert_eq!(stats.min_damage, 0);
+        assert_eq!(stats.max_damage, 14);
+        assert_eq!(stats.min_hit_damage, 3);
+
+        let always_hits = Target {
+            hit_roll: HitRoll::AlwaysHitsNoCrits,
+            ..target(15)
+        };
+        let stats = calc_build_stats(&build, &always_hits, 0, &mut DiceCache::default());
+        assert_eq!(
+            (stats.min_damage, stats.max_damage, stats.min_hit_damage),
+            (3, 8, 3)
+        );
+    }
+
+    #[test]
+    fn symmetric_distributions_have_no_skew() {
+        assert_close(skewness(&PMF::from([(2, 0.25), (5, 0.5), (8, 0.25)])), 0.0);
+        assert_close(skewness(&die_pmf(6, 1)), 0.0);
+        assert_close(skewness(&convolve(&die_pmf(8, 1), &die_pmf(8, 1))), 0.0);
+        assert_close(skewness(&PMF::from([(7, 1.0)])), 0.0);
+
+        // Rare big hits make for a long right tail.
+        assert!(skewness(&PMF::from([(0, 0.9), (10, 0.1)])) > 0.0);
+    }
+
+    #[test]
+    fn chance_within_includes_both_ends() {
+        let pmf = PMF::from([(0, 0.25), (5, 0.5), (10, 0.25)]);
+        assert_close(chance_within(&pmf, 5.0, 10.0), 0.75);
+        assert_close(chance_within(&pmf, 0.5, 9.5), 0.5);
+        assert_close(chance_within(&pmf, 11.0, 20.0), 0.0);
+
+        // One std dev of about 3.54 around 5 leaves out both ends.
+        let stats = pmf_stats(pmf, &target(10), 0);
+        assert_close(stats.std_dev, 12.5f64.sqrt());
+        assert_close(stats.within_std_dev_chance, 0.5);
+    }
+
+    #[test]
+    fn miss_heavy_builds_rarely_beat_their_mean() {
+        // Only hits on a 19 or 20, which is all it takes to be above a mean of 1.
+        let build = Build {
+            attacks: vec![flat_attack(0, 10)],
+            ..Build::default()
+        };
+        let stats = calc_build_stats(&build, &target(19), 0, &mut DiceCache::default());
+        assert_close(stats.mean, 1.0);
+        assert_close(stats.above_mean_chance, 0.1);
+
+        // With dice the misses still drag the mean below every hit.
+        let build = Build::default();
+        let stats = calc_build_stats(&build, &target(28), 0, &mut DiceCache::default());
+        assert_close(stats.above_mean_chance, 0.15);
+    }
+
+    #[test]
+    fn rounds_add_up() {
+        let ten = PMF::from([(10, 1.0)]);
+        assert_fixed(&repeat_rounds(&ten, 0), 0);
+        assert_fixed(&repeat_rounds(&ten, 1), 10);
+        assert_fixed(&repeat_rounds(&ten, 2), 20);
+        assert_fixed(&repeat_rounds(&ten, 7), 70);
+        assert_fixed(&nova_rounds(&PMF::from([(25, 1.0)]), &ten, 3), 45);
+
+        // Independent coin flips of 10 over three rounds are a binomial.
+        let coin = PMF::from([(0, 0.5), (10, 0.5)]);
+        let three = repeat_rounds(&coin, 3);
+        for (dmg, prob) in [(0, 1.0), (10, 3.0), (20, 3.0), (30, 1.0)] {
+            assert_close(three[&dmg], prob / 8.0);
+        }
+    }
+
+    #[test]
+    fn identical_builds_tie_a_lot() {
+        let coin = PMF::from([(0, 0.5), (10, 0.5)]);
+        let (win, tie, lose) = outcome_probabilities(&coin, &coin);
+        assert_close(win, 0.25);
+        assert_close(tie, 0.5);
+        assert_close(lose, 0.25);
+
+        // Against a high AC both builds mostly miss, so a tie at 0 is the likeliest outcome.
+        let pmf =
+            calc_build_stats(&Build::default(), &target(28), 0, &mut DiceCache::default()).pmf;
+        let (win, tie, lose) = outcome_probabilities(&pmf, &pmf);
+        assert_close(win + tie + lose, 1.0);
+        assert_close(win, lose);
+        assert!(tie > 0.5);
+    }
+
+    #[test]
+    fn any_hit_is_one_minus_all_missing() {
+        // +7 against AC 15 hits on an 8, 65% of the time.
+        let build = Build {
+            attacks: vec![flat_attack(7, 1); 2],
+            ..Build::default()
+        };
+        assert_close(any_hit_chance(&build, &target(15)), 0.8775);
+        let stats = calc_build_stats(&build, &target(15), 0, &mut DiceCache::default());
+        assert_close(stats.any_hit_chance, 0.8775);
+    }
+
+    #[test]
+    fn hit_counts_of_coin_flip_attacks_are_binomial() {
+        // +4 against AC 15 hits on an 11, crits included.
+        let build = Build {
+            attacks: vec![flat_attack(4, 1); 3],
+            ..Build::default()
+        };
+        let hit_counts = hit_count_pmf(&build, &target(15));
+        for (hits, ways) in [(0, 1.0), (1, 3.0), (2, 3.0), (3, 1.0)] {
+            assert_close(hit_counts[&hits], ways / 8.0);
+        }
+        assert_close(hit_counts.values().sum(), 1.0);
+    }
+
+    #[test]
+    fn first_hit_bonus_needs_a_hit() {
+        let build = Build {
+            attacks: vec![flat_attack(4, 3); 2],
+            first_hit_bonus: 5,
+            ..Build::default()
+        };
+        let stats = calc_build_stats(&build, &target(15), 0, &mut DiceCache::default());
+        // Two misses deal nothing at all, the bonus only comes with the first hit.
+        assert_close(stats.pmf[&0], 0.25);
+        assert_close(stats.pmf[&8], 0.5);
+        assert_close(stats.pmf[&11], 0.25);
+        assert_close(stats.pmf.values().sum(), 1.0);
+    }
+
+    #[test]
+    fn savage_attacker_rerolls_a_single_attack() {
+        let mut small = flat_attack(5, 0);
+        small.dice[0].1 = 1;
+        let mut big = flat_attack(5, 0);
+        big.dice[3].1 = 2;
+        let build = Build {
+            attacks: vec![small, big],
+            savage: true,
+            ..Build::default()
+        };
+        let dice_pmfs: Vec<_> = build
+            .attacks
+            .iter()
+            .map(|a| attack_dice_pmfs(a, &target(15), &build))
+            .collect();
+        let attack_pmfs: Vec<_> = build
+            .attacks
+            .iter()
+            .zip(&dice_pmfs)
+            .map(|(a, dice)| attack_pmf(a, &dice.regular, &target(15), &build))
+            .collect();
+
+        // The 2d10 gains more from the reroll than the d4, so it's the one to get it.
+        let (i, savage_pmf) = savage_attack(&build, &dice_pmfs, &attack_pmfs, &target(15)).unwrap();
+        assert_eq!(i, 1);
+        let (base, crit) = &dice_pmfs[1].regular;
+        let best = (best_of_two(base), best_of_two(crit));
+        assert_eq!(
+            savage_pmf,
+            attack_pmf(&build.attacks[1], &best, &target(15), &build)
+        );
+
+        let stats = calc_build_stats(&build, &target(15), 0, &mut DiceCache::default());
+        assert_close(stats.mean, mean(&attack_pmfs[0]) + mean(&savage_pmf));
+    }
+
+    #[test]
+    fn savage_per_die_beats_rerolling_the_total() {
+        let mut attack = flat_attack(5, 0);
+        attack.dice[1].1 = 1;
+        let whole = best_of_two(&typed_dmg_pmf(&attack, 1, &target(15), false));
+        // With a single die both are the same thing.
+        assert_eq!(best_of_two_per_die(&attack, 1, &target(15)), whole);
+
+        attack.dice[1].1 = 2;
+        let whole = best_of_two(&typed_dmg_pmf(&attack, 1, &target(15), false));
+        let per_die = best_of_two_per_die(&attack, 1, &target(15));
+        assert_ne!(per_die, whole);
+        // Every die keeps the better of its own two rolls.
+        let max_of_two: f64 = (1..=6)
+            .map(|face| (2 * face - 1) as f64 * face as f64)
+            .sum();
+        assert_close(mean(&per_die), 2.0 * max_of_two / 36.0);
+        assert!(mean(&per_die) > mean(&whole));
+    }
+
+    #[test]
+    fn min_face_moves_low_rolls_up() {
+        // A 1 counts as a 2, so the faces add up to 2 + 2 + 3 + 4 + 5 + 6 = 22.
+        let d6 = die_pmf(6, 2);
+        assert_close(d6[&2], 2.0 / 6.0);
+        assert!(!d6.contains_key(&1));
+        assert_close(mean(&d6), 22.0 / 6.0);
+
+        // Crits roll the floored dice too.
+        let mut attack = flat_attack(5, 0);
+        attack.dice[1].1 = 1;
+        attack.min_face = 2;
+        assert_close(
+            mean(&typed_dmg_pmf(&attack, 1, &target(15), false)),
+            22.0 / 6.0,
+        );
+        assert_close(
+            mean(&typed_dmg_pmf(&attack, 2, &target(15), false)),
+            44.0 / 6.0,
+        );
+    }
+
+    #[test]
+    fn custom_dice_roll_like_any_other() {
+        assert_close(mean(&die_pmf(3, 1)), 2.0);
+
+        let mut attack = flat_attack(5, 0);
+        attack.custom_dice.push((3, 1, attack.flat_type));
+        assert_close(mean(&typed_dmg_pmf(&attack, 1, &target(15), false)), 2.0);
+        assert_close(mean(&typed_dmg_pmf(&attack, 2, &target(15), false)), 4.0);
+    }
+
+    #[test]
+    fn pinned_acs_match_the_means_chart() {
+        let build = Build {
+            attacks: vec![Attack::default(), flat_attack(3, 6)],
+            ..Build::default()
+        };
+        let mut cache = DiceCache::default();
+        let means = calc_build_means(&build, &target(10), 10..=23, &mut cache);
+        let pinned_acs = [13, 18, 23];
+        let pinned = calc_pinned_stats(&build, &target(10), 0, &pinned_acs, &mut cache);
+        assert_eq!(pinned.len(), pinned_acs.len());
+        for (ac, stats) in pinned_acs.into_iter().zip(pinned) {
+            assert_close(stats.mean, means[(ac - 10) as usize].0);
+        }
+    }
+
+    #[test]
+    fn encounter_adds_up_distinct_rounds() {
+        let fixed = PMF::from([(10, 1.0)]);
+        let coin = PMF::from([(0, 0.5), (4, 0.5)]);
+        let stats = calc_encounter_stats(&[&fixed, &coin]);
+
+        assert_eq!(stats.pmf.len(), 2);
+        assert_close(stats.pmf[&10], 0.5);
+        assert_close(stats.pmf[&14], 0.5);
+        assert_close(stats.mean, 12.0);
+        assert_close(stats.std_dev, 2.0);
+    }
+
+    #[test]
+    fn d20_dmg_die_crits_and_takes_savage_attacker() {
+        let mut attack = flat_attack(5, 0);
+        attack.dice[4].1 = 1;
+        let hit = typed_dmg_pmf(&attack, 1, &target(15), false);
+        assert_close(mean(&hit), 10.5);
+        assert_eq!(damage_bounds(&hit), (1, 20, 1));
+        // A crit rolls 2d20.
+        let crit = typed_dmg_pmf(&attack, 2, &target(15), false);
+        assert_close(mean(&crit), 21.0);
+        assert_eq!(damage_bounds(&crit), (2, 40, 2));
+
+        let build = Build {
+            attacks: vec![attack.clone()],
+            savage: true,
+            ..Build::default()
+        };
+        let dice = attack_dice_pmfs(&attack, &target(15), &build);
+        let (savage_hit, savage_crit) = dice.savage.unwrap();
+        assert_eq!(savage_hit, best_of_two(&hit));
+        assert_eq!(savage_crit, best_of_two(&crit));
+        // The better of two d20s averages (2 * 2870 - 210) / 400.
+        assert_close(mean(&savage_hit), 13.825);
+    }
 }
@@ -0,0 +1,215 @@
+use eframe::egui::{self, Align2, Key, Modifiers};
+
+use crate::AcComparison;
+use crate::Dnd;
+use crate::presets;
+use crate::{build_name, clear_confirm_id, export_ac_table, export_csv, export_report};
+use crate::{load_json, save_json};
+
+/// Something that can be run from the command palette.
+pub struct Action {
+    pub name: String,
+    pub run: Box<dyn Fn(&mut Dnd, &egui::Context)>,
+}
+
+impl Action {
+    fn new(name: impl Into<String>, run: impl Fn(&mut Dnd, &egui::Context) + 'static) -> Self {
+        Self {
+            name: name.into(),
+            run: Box::new(run),
+        }
+    }
+}
+
+/// Every action that makes sense right now, the per build ones only for builds that exist.
+pub fn actions(dnd: &Dnd) -> Vec<Action> {
+    let build_count = dnd.builds.len();
+    let mut actions = Vec::new();
+
+    for idx in 0..build_count {
+        let name = build_name(idx);
+        actions.push(Action::new(
+            format!("Add attack to {name}"),
+            move |dnd, _| {
+                let slot = &mut dnd.builds[idx];
+                slot.build.add_attack();
+                slot.changed = true;
+            },
+        ));
+        actions.push(Action::new(format!("Undo {name}"), move |dnd, _| {
+            let slot = &mut dnd.builds[idx];
+            slot.changed |= slot.history.undo(&mut slot.build);
+        }));
+        actions.push(Action::new(format!("Redo {name}"), move |dnd, _| {
+            let slot = &mut dnd.builds[idx];
+            slot.changed |= slot.history.redo(&mut slot.build);
+        }));
+        // Goes through the same confirmation as the Clear button of the build.
+        actions.push(Action::new(format!("Clear {name}"), move |_, ctx| {
+            ctx.data_mut(|d| d.insert_temp(clear_confirm_id(idx), true));
+        }));
+        actions.push(Action::new(
+            format!("Export CSV of {name}"),
+            move |dnd, _| {
+                export_csv(&dnd.builds[idx].stats);
+            },
+        ));
+        if build_count > 1 {
+            actions.push(Action::new(format!("Remove {name}"), move |dnd, _| {
+                dnd.remove_build(idx);
+            }));
+        }
+    }
+    for from in 0..build_count {
+        for to in 0..build_count {
+            if from == to {
+                continue;
+            }
+            let (from_name, to_name) = (build_name(from), build_name(to));
+            actions.push(Action::new(
+                format!("Copy {from_name} to {to_name}"),
+                move |dnd, _| {
+                    dnd.copy_build(from, to);
+                },
+            ));
+            if from < to {
+                actions.push(Action::new(
+                    format!("Swap {from_name} and {to_name}"),
+                    move |dnd, _| {
+                        dnd.swap_builds(from, to);
+                    },
+                ));
+            }
+        }
+    }
+    actions.push(Action::new("Add build", |dnd, _| dnd.add_build()));
+
+    for &(cr, ac) in presets::CR_ACS {
+        actions.push(Action::new(
+            format!("Target CR {cr} (AC {ac})"),
+            move |dnd, _| {
+                dnd.target.ac = ac;
+                dnd.mark_all_changed();
+            },
+        ));
+    }
+    actions.push(Action::new("Toggle AC rule", |dnd, _| {
+        dnd.target.ac_comparison = match dnd.target.ac_comparison {
+            AcComparison::MeetsOrBeats => AcComparison::MustExceed,
+            AcComparison::MustExceed => AcComparison::MeetsOrBeats,
+        };
+        dnd.mark_all_changed();
+    }));
+    actions.push(Action::new("Add encounter round", |dnd, _| {
+        let last = dnd.encounter_rounds.last().copied().unwrap_or(0);
+        dnd.encounter_rounds.push(last);
+        dnd.encounter_changed = true;
+    }));
+
+    actions.push(Action::new("Save session", |dnd, _| {
+        save_json(&dnd.session(), "session.json", "session");
+    }));
+    actions.push(Action::new("Load session", |dnd, _| {
+        if let Some(session) = load_json("session") {
+            dnd.load_session(session);
+        }
+    }));
+    actions.push(Action::new("Export AC table", |dnd, _| {
+        export_ac_table(&dnd.all_means());
+    }));
+    actions.push(Action::new("Export Markdown report", |dnd, _| {
+        export_report(&dnd.builds, &dnd.target, dnd.rounds);
+    }));
+    actions.push(Action::new("Toggle theme", |dnd, _| {
+        dnd.dark_mode = !dnd.dark_mode;
+    }));
+    actions
+}
+
+#[derive(Default)]
+pub struct Palette {
+    open: bool,
+    query: String,
+    selected: usize,
+}
+
+/// Command palette toggled with Ctrl+P. Arrow keys pick an action and Enter runs it.
+pub fn show(ctx: &egui::Context, dnd: &mut Dnd) {
+    if ctx.input_mut(|i| i.consume_key(Modifiers::COMMAND, Key::P)) {
+        dnd.palette.open = !dnd.palette.open;
+        dnd.palette.query.clear();
+        dnd.palette.selected = 0;
+    }
+    if !dnd.palette.open {
+        return;
+    }
+
+    // Consume the navigation keys before the text edit gets a chance to see them.
+    let (up, down, enter, escape) = ctx.input_mut(|i| {
+        (
+            i.consume_key(Modifiers::NONE, Key::ArrowUp),
+            i.consume_key(Modifiers::NONE, Key::ArrowDown),
+            i.consume_key(Modifiers::NONE, Key::Enter),
+            i.consume_key(Modifiers::NONE, Key::Escape),
+        )
+    });
+    if escape {
+        dnd.palette.open = false;
+        return;
+    }
+
+    let actions = actions(dnd);
+    let query = dnd.palette.query.to_lowercase();
+    let matches: Vec<&Action> = actions
+        .iter()
+        .filter(|action| action.name.to_lowercase().contains(&query))
+        .collect();
+
+    let palette = &mut dnd.palette;
+    let last = matches.len().saturating_sub(1);
+    if down {
+        palette.selected += 1;
+    }
+    if up {
+        palette.selected = palette.selected.saturating_sub(1);
+    }
+    palette.selected = palette.selected.min(last);
+
+    let mut chosen = matches.get(palette.selected).copied().filter(|_| enter);
+
+    egui::Window::new("Command palette")
+        .title_bar(false)
+        .collapsible(false)
+        .resizable(false)
+        .anchor(Align2::CENTER_TOP, [0.0, 80.0])
+        .show(ctx, |ui| {
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut palette.query)
+                    .hint_text("Type to search actions...")
+                    .desired_width(400.0),
+            );
+            response.request_focus();
+            if response.changed() {
+                palette.selected = 0;
+            }
+
+            for (i, &action) in matches.iter().enumerate() {
+                if ui
+                    .selectable_label(i == palette.selected, action.name.as_str())
+                    .clicked()
+                {
+                    chosen = Some(action);
+                }
+            }
+            if matches.is_empty() {
+                ui.label("No matching actions");
+            }
+        });
+
+    if let Some(action) = chosen {
+        dnd.palette.open = false;
+        (action.run)(dnd, ctx);
+        // Recompute flags are only looked at in the next frame.
+        ctx.request_repaint();
+    }
+}
@@ -0,0 +1,169 @@
+use crate::Attack;
+use crate::DAMAGE_TYPES;
+use crate::Die;
+
+/// Reads an attack written the way stat blocks and character sheets do, like
+/// "1d8+4 slashing, +7 to hit". The parts are separated by commas and can come in any order.
+/// Every damage term has to be a dice roll (`NdM`, `dM`) or a flat number, all of them of the
+/// same damage type. Missing parts keep the defaults of a new attack.
+pub fn parse_attack(text: &str) -> Result<Attack, String> {
+    let mut attack = Attack {
+        flat: 0,
+        ..Attack::default()
+    };
+    for (_, count, _) in &mut attack.dice {
+        *count = 0;
+    }
+
+    let mut has_dmg = false;
+    for part in text
+        .split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+    {
+        let part = part.to_lowercase();
+        if let Some(ab) = part.strip_suffix("to hit") {
+            let ab = ab.trim().trim_start_matches('+').trim();
+            attack.ab = ab
+                .parse()
+                .map_err(|_| format!("invalid attack bonus \"{ab}\""))?;
+            continue;
+        }
+        if has_dmg {
+            return Err(format!("more than one damage part in \"{part}\""));
+        }
+        parse_dmg(&part, &mut attack)?;
+        has_dmg = true;
+    }
+
+    if !has_dmg {
+        return Err("no damage found".to_string());
+    }
+    Ok(attack)
+}
+
+fn parse_dmg(part: &str, attack: &mut Attack) -> Result<(), String> {
+    let dmg_type = DAMAGE_TYPES
+        .iter()
+        .find(|dmg_type| part.ends_with(&dmg_type.label().to_lowercase()));
+    let terms = match dmg_type {
+        Some(&dmg_type) => {
+            attack.flat_type = dmg_type;
+            for (_, _, die_type) in &mut attack.dice {
+                *die_type = dmg_type;
+            }
+            &part[..part.len() - dmg_type.label().len()]
+        }
+        None => part,
+    };
+
+    let terms: String = terms.chars().filter(|c| !c.is_whitespace()).collect();
+    let mut flat: u32 = 0;
+    for term in terms.split('+').filter(|term| !term.is_empty()) {
+        let Some((count, sides)) = term.split_once('d') else {
+            flat += term
+                .parse::<u32>()
+                .map_err(|_| format!("invalid damage term \"{term}\""))?;
+            continue;
+        };
+
+        let count = if count.is_empty() {
+            1
+        } else {
+            count
+                .parse::<u8>()
+                .map_err(|_| format!("invalid dice count in \"{term}\""))?
+        };
+        let sides = sides
+            .parse::<u32>()
+            .ok()
+            .filter(|&sides| sides > 0)
+            .ok_or_else(|| format!("invalid die in \"{term}\""))?;
+        match Die::try_from(sides) {
+            Ok(die) => {
+                let entry = attack.dice.iter_mut().find(|(d, _, _)| *d == die);
+                if let Some((_, total, _)) = entry {
+                    *total = total.saturating_add(count);
+                }
+            }
+            Err(_) => attack.custom_dice.push((sides, count, attack.flat_type)),
+        }
+    }
+
+    attack.flat = u8::try_from(flat).map_err(|_| format!("flat damage {flat} is too big"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DamageType;
+
+    fn dice_count(attack: &Attack, die: Die) -> u8 {
+        attack
+            .dice
+            .iter()
+            .find(|(d, _, _)| *d == die)
+            .map_or(0, |&(_, count, _)| count)
+    }
+
+    #[test]
+    fn reads_a_stat_block_attack() {
+        let attack = parse_attack("1d8+4 slashing, +7 to hit").unwrap();
+        assert_eq!(attack.ab, 7);
+        assert_eq!(attack.flat, 4);
+        assert_eq!(attack.flat_type, DamageType::Slashing);
+        assert_eq!(dice_count(&attack, Die::D8), 1);
+        assert_eq!(
+            attack.dice.iter().map(|&(_, count, _)| count).sum::<u8>(),
+            1
+        );
+        assert!(attack.custom_dice.is_empty());
+    }
+
+    #[test]
+    fn ignores_whitespace_and_order() {
+        let attack = parse_attack("  +5 TO HIT ,2d6 + d4+ 3  Fire , ").unwrap();
+        assert_eq!(attack.ab, 5);
+        assert_eq!(attack.flat, 3);
+        assert_eq!(dice_count(&attack, Die::D6), 2);
+        assert_eq!(dice_count(&attack, Die::D4), 1);
+        assert!(
+            attack
+                .dice
+                .iter()
+                .all(|&(_, _, dmg_type)| dmg_type == DamageType::Fire)
+        );
+    }
+
+    #[test]
+    fn missing_parts_keep_the_defaults() {
+        let attack = parse_attack("1d10 piercing").unwrap();
+        assert_eq!(attack.ab, Attack::default().ab);
+        assert_eq!(attack.flat, 0);
+        assert_eq!(dice_count(&attack, Die::D10), 1);
+
+        // Without a type the dmg keeps the default one.
+        let attack = parse_attack("3d8").unwrap();
+        assert_eq!(attack.flat_type, Attack::default().flat_type);
+        assert_eq!(dice_count(&attack, Die::D8), 3);
+    }
+
+    #[test]
+    fn odd_dice_become_custom_dice() {
+        let attack = parse_attack("1d12 + 2d8 bludgeoning").unwrap();
+        assert_eq!(attack.custom_dice, vec![(12, 1, DamageType::Bludgeoning)]);
+        assert_eq!(dice_count(&attack, Die::D8), 2);
+    }
+
+    #[test]
+    fn rejects_what_it_cant_read() {
+        assert!(parse_attack("").is_err());
+        assert!(parse_attack("+7 to hit").is_err());
+        assert!(parse_attack("1d8+x slashing").is_err());
+        assert!(parse_attack("1d0").is_err());
+        assert!(parse_attack("1d8, 1d6").is_err());
+        assert!(parse_attack("1d8, seven to hit").is_err());
+        assert!(parse_attack("1d8+300").is_err());
+    }
+}
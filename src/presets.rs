@@ -0,0 +1,37 @@
+/// Typical AC of a monster for each challenge rating, from the monster creation guidelines.
+pub const CR_ACS: &[(&str, u8)] = &[
+    ("0", 13),
+    ("1/8", 13),
+    ("1/4", 13),
+    ("1/2", 13),
+    ("1", 13),
+    ("2", 13),
+    ("3", 13),
+    ("4", 14),
+    ("5", 15),
+    ("6", 15),
+    ("7", 15),
+    ("8", 16),
+    ("9", 16),
+    ("10", 17),
+    ("11", 17),
+    ("12", 17),
+    ("13", 18),
+    ("14", 18),
+    ("15", 18),
+    ("16", 18),
+    ("17", 19),
+    ("18", 19),
+    ("19", 19),
+    ("20", 19),
+    ("21", 19),
+    ("22", 19),
+    ("23", 19),
+    ("24", 19),
+    ("25", 19),
+    ("26", 19),
+    ("27", 19),
+    ("28", 19),
+    ("29", 19),
+    ("30", 19),
+];